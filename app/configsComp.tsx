@@ -39,6 +39,11 @@ const ConfigComp = ({
       const res = await invoke<string>('connect_config', { data: JSON.stringify(config) });
       setConnection(config);
       toast.success("Database connection has been established.");
+      // Schema embeddings that could not be loaded leave the connection working without them
+      const { warning } = JSON.parse(res) as { warning: string | null };
+      if (warning) {
+        toast.warning(warning);
+      }
       setLoading(false);
       setOpen(false); // Close the dialog after successful connection
     } catch (e) {
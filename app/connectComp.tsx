@@ -34,6 +34,7 @@ import { toast } from "sonner"
 const ConnectComp = ({ connection, setConnection }: { connection: Config | null, setConnection: (config: Config) => void }) => {
     const { Configs, deleteConfig, selectedConfig, setSelectedConfig, onEdit, setOnEdit, upsertConfig } = useLocalStorage();
     const [open, setOpen] = useState<boolean>(false);
+    const [annotations, setAnnotations] = useState<string | null>(null);
 
     // Handlers for setting form values
     const sqlKnowledgeHandler = (e: { target: { value: string } }) => {
@@ -49,6 +50,55 @@ const ConnectComp = ({ connection, setConnection }: { connection: Config | null,
         }))
     }
 
+    const schemasHandler = (e: { target: { value: string } }) => {
+        setSelectedConfig((prev: Config) => ({
+            ...prev,
+            schemas: e.target.value.split(",").map((schema) => schema.trim()).filter((schema) => schema)
+        }))
+    }
+
+    /**
+     * Reads the schema of the connected database again, e.g. after a migration.
+     */
+    const refreshSchema = async () => {
+        try {
+            const { invoke } = await import("@tauri-apps/api");
+            const res = JSON.parse(await invoke<string>('refresh_schema')) as { tables: number, changed: boolean };
+            toast.success(res.changed
+                ? "Schema updated, " + res.tables + " tables found."
+                : "Schema is up to date.");
+        } catch (e) {
+            toast.error("Schema cannot be refreshed. " + JSON.stringify(e));
+        }
+    }
+
+    /**
+     * Loads the local annotations of the connected database for editing.
+     */
+    const editAnnotations = async () => {
+        try {
+            const { invoke } = await import("@tauri-apps/api");
+            const res = JSON.parse(await invoke<string>('get_annotations'));
+            setAnnotations(JSON.stringify(res, null, 2));
+        } catch (e) {
+            toast.error("Annotations cannot be read. " + JSON.stringify(e));
+        }
+    }
+
+    /**
+     * Saves the annotations, they are shown to the AI model as table and column comments.
+     */
+    const saveAnnotations = async () => {
+        try {
+            const { invoke } = await import("@tauri-apps/api");
+            await invoke<string>('save_annotations', { data: annotations });
+            setAnnotations(null);
+            toast.success("Annotations saved.");
+        } catch (e) {
+            toast.error("Annotations cannot be saved. " + JSON.stringify(e));
+        }
+    }
+
     /**
      * Opens a file selection dialog and sets the AI model path if selected.
      */
@@ -159,6 +209,24 @@ const ConnectComp = ({ connection, setConnection }: { connection: Config | null,
                             <p className="text-muted-foreground text-xs mt-2">Please provide the connection string for the database.</p>
                         </div>
 
+                        {/* Optional Schemas Input, SQLite has a single schema */}
+                        {selectedConfig.db_type && selectedConfig.db_type != "SQLite" && (
+                            <div className="items-center gap-4">
+                                <Label htmlFor="schemas" className="text-right">{selectedConfig.db_type == "MySQL" ? "Databases" : "Schemas"} (Optional):</Label>
+                                <Input id="schemas" defaultValue={(selectedConfig.schemas || []).join(", ")} onChange={schemasHandler} placeholder={selectedConfig.db_type == "MySQL" ? "shop, billing" : "app, billing, audit"} className="col-span-3" />
+                                <p className="text-muted-foreground text-xs mt-2">Comma separated list of {selectedConfig.db_type == "MySQL" ? "databases" : "schemas"} to query, leave empty to use {selectedConfig.db_type == "MySQL" ? "the database of the connection string" : "public"}.</p>
+                            </div>
+                        )}
+
+                        {/* Sample Data Switch, off for databases holding sensitive data */}
+                        <div className="items-center gap-4">
+                            <div className="flex items-center">
+                                <input id="sample_data" type="checkbox" checked={selectedConfig.sample_data !== false} onChange={(e) => setSelectedConfig(prev => ({ ...prev, sample_data: e.target.checked }))} className="mr-2" />
+                                <Label htmlFor="sample_data">Show sample data to the AI model</Label>
+                            </div>
+                            <p className="text-muted-foreground text-xs mt-2">Example rows and column values help the model write correct filters. Turn it off for sensitive data.</p>
+                        </div>
+
                         {/* AI Model File Selector */}
                         <div className='flex items-center'>
                             <Button variant="outline" onClick={handleFileSelect}>Choose AI Model</Button>
@@ -175,8 +243,21 @@ const ConnectComp = ({ connection, setConnection }: { connection: Config | null,
                     </div>
                 )}
 
+                {/* Annotations Editor, comments for tables and columns the database does not document */}
+                {!onEdit && annotations !== null && (
+                    <div className="items-center gap-4">
+                        <Label htmlFor="annotations" className="text-right">Annotations:</Label>
+                        <Textarea id="annotations" value={annotations} onChange={(e) => setAnnotations(e.target.value)} rows={10} className="col-span-3 font-mono text-xs" />
+                        <p className="text-muted-foreground text-xs mt-2">{'For example {"tables": {"payments": {"comment": "Card payments", "columns": {"amt": "in cents"}}}}'}</p>
+                    </div>
+                )}
+
                 {/* Save or Create New Config */}
                 <DialogFooter>
+                    {!onEdit && connection && <Button variant="outline" onClick={refreshSchema}>Refresh schema</Button>}
+                    {!onEdit && connection && (annotations === null
+                        ? <Button variant="outline" onClick={editAnnotations}>Annotations</Button>
+                        : <Button variant="outline" onClick={saveAnnotations}>Save annotations</Button>)}
                     {onEdit ? <Button onClick={save}>Save</Button> : <Button onClick={newConfig}>New</Button>}
                 </DialogFooter>
             </DialogContent>
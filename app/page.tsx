@@ -3,6 +3,7 @@
 import Image from 'next/image';
 import QueryComp from "./queryComp";
 import ConnectComp from "./connectComp";
+import SchemaComp from "./schemaComp";
 import { useEffect, useRef } from "react";
 import useLocalStorage from "./useLocalStorage";
 import { Button } from "@/components/ui/button";
@@ -53,6 +54,8 @@ export default function Home() {
       <header className="w-full flex text-start gap-2 full items-center pl-8 py-4 shadow-sm">
         {/* Component to manage the database connection */}
         <ConnectComp connection={connection} setConnection={setConnection} />
+        {/* Browser for the tables of the connected database */}
+        <SchemaComp connection={connection} />
       </header>
 
       {/* Display instructions if no results are available */}
@@ -90,25 +93,56 @@ export default function Home() {
                 </div>
               )}
 
+              {/* Display the tables the model was shown */}
+              {resultObj.tables && resultObj.tables.length > 0 && (
+                <div className="message text-sm text-muted-foreground">
+                  <strong>Tables:</strong> <span className="font-mono">{resultObj.tables.join(", ")}</span>
+                </div>
+              )}
+
+              {/* Display the queries tried before the answer, with their errors */}
+              {resultObj.attempts && (resultObj.attempts.length > 1 || resultObj.err) && (
+                <div className="message text-sm text-muted-foreground">
+                  <strong>Attempts:</strong>
+                  <ol className="list-decimal ml-6">
+                    {resultObj.attempts.map((attempt, attemptIndex) => (
+                      <li key={attemptIndex}>
+                        <span className="font-mono">{attempt.sql}</span>
+                        {attempt.error && <div className="text-red-500">{attempt.error}</div>}
+                      </li>
+                    ))}
+                  </ol>
+                </div>
+              )}
+
               {/* Display table of results if data is available */}
-              {Array.isArray(resultObj.data) && resultObj.data.length > 0 && (
+              {resultObj.data && Array.isArray(resultObj.data.rows) && resultObj.data.columns.length > 0 && (
                 <div className="m-4 p-10 bg-zinc-50 rounded-lg border">
+                  <div className="text-sm text-muted-foreground mb-2">
+                    {resultObj.data.row_count} rows in {resultObj.data.elapsed_ms} ms
+                  </div>
+                  {resultObj.data.warnings && resultObj.data.warnings.map((warning, warningIndex) => (
+                    <div key={warningIndex} className="text-sm text-amber-600 mb-2">{warning}</div>
+                  ))}
                   <Table className="overflow-hidden">
                     <TableHeader>
                       <TableRow>
-                        {Object.keys(resultObj.data[0]).map((key) => (
-                          <TableHead key={key} className="w-[100px]">{key}</TableHead>
+                        {resultObj.data.columns.map((column, columnIndex) => (
+                          <TableHead key={columnIndex} className="w-[100px]" title={column.db_type + (column.nullable === false ? " not null" : "")}>{column.name}{column.lossy && " ⚠"}</TableHead>
                         ))}
                       </TableRow>
                     </TableHeader>
                     <TableBody>
-                      {resultObj.data.map((line: unknown, lineIndex) => (
+                      {resultObj.data.rows.map((line, lineIndex) => (
                         <TableRow key={lineIndex}>
-                          {typeof line === 'object' && line !== null && Object.entries(line).map(([key, value]) => (
-                            <TableCell key={key} className="font-medium">
-                              {(typeof value === 'string' || typeof value === 'number' || typeof value === 'boolean') ? value : 'N/A'}
-                            </TableCell>
-                          ))}
+                          {line.map((value, valueIndex) => {
+                            const lossy = resultObj.data?.lossy_cells?.find(cell => cell.row === lineIndex && cell.column === valueIndex);
+                            return (
+                              <TableCell key={valueIndex} className="font-medium" title={lossy && (lossy.encoding === "text" ? "Unsupported type, shown as text" : "Unsupported type, shown as base64 bytes")}>
+                                {(typeof value === 'string' || typeof value === 'number' || typeof value === 'boolean') ? String(value) : value === null ? 'NULL' : JSON.stringify(value)}{lossy && " ⚠"}
+                              </TableCell>
+                            );
+                          })}
                         </TableRow>
                       ))}
                     </TableBody>
@@ -37,6 +37,8 @@ const QueryComp = ({ setResults, connection }: {
     const [queryTab, setQueryTab] = useState<string>("ask");
     const [sql, setSql] = useState<string>("");
     const [question, setQuestion] = useState<string>("");
+    const [streamed, setStreamed] = useState<string>("");
+    const [requestId, setRequestId] = useState<string | null>(null);
 
     // Handler for setting the question from the input
     const questionHandler = (e: { target: { value: React.SetStateAction<string>; }; }) => {
@@ -48,6 +50,43 @@ const QueryComp = ({ setResults, connection }: {
         setSql(e.target.value)
     }
 
+    /**
+     * Show an error returned by a command, a cancelled request is not reported as a failure.
+     */
+    const showError = (e: unknown) => {
+        if (e === "Cancelled") {
+            toast.info("Request cancelled.")
+            return;
+        }
+        const msg = typeof e === "string" ? e : Object.entries(e as { [key: string]: unknown }).map(([key, value]) => key + " : " + (typeof value === "string" ? value : JSON.stringify(value))).join(', ');
+        toast.error(msg)
+    }
+
+    /**
+     * Stop the running request, its command then fails with a `Cancelled` error.
+     */
+    const cancel = async () => {
+        if (!requestId) {
+            return;
+        }
+        const { invoke } = await import("@tauri-apps/api");
+        await invoke<string>('cancel', { requestId: requestId });
+    }
+
+    /**
+     * Listen for model output streamed for the given request and show it while it is generated.
+     * Returns the function that stops listening.
+     */
+    const listenForTokens = async (requestId: string) => {
+        const { listen } = await import("@tauri-apps/api/event");
+        setStreamed("");
+        return listen<{ request_id: string, token: string }>("ai://token", (event) => {
+            if (event.payload.request_id === requestId) {
+                setStreamed((prev) => prev + event.payload.token);
+            }
+        });
+    }
+
     /**
      * Function to ask a question and retrieve results from the database.
      * It invokes the backend API to process the question.
@@ -65,22 +104,34 @@ const QueryComp = ({ setResults, connection }: {
             toast.error("Error: Please choose an AI model file before asking!")
             return;
         }
+        const requestId = crypto.randomUUID();
+        setRequestId(requestId);
+        const unlisten = await listenForTokens(requestId);
         try {
             const { invoke } = await import("@tauri-apps/api");
 
-            const res = await invoke<string>('ask', { data: JSON.stringify(connection), question: question });
+            const res = await invoke<string>('ask', { data: JSON.stringify(connection), question: question, requestId: requestId });
             const json: Result = JSON.parse(res) as Result;
 
             setResults((prevResults: Result[]) => [...prevResults, json]);
             setLoading(false);
 
-            if (Array.isArray(json.data)) {
-                toast.info("Success! Retrieved " + json.data.length + " lines of data.")
+            if (json.data) {
+                toast.info("Success! Retrieved " + json.data.row_count + " lines of data in " + json.data.elapsed_ms + " ms.")
             }
         } catch (e) {
-            const msg = Object.entries(e as { [key: string]: string }).map(([key, value]) => key + " : " + value).join(', ');
             setLoading(false);
-            toast.error(msg)
+            // Keep the queries the model tried when it could not repair them
+            const failed = (e as { RepairFailed?: { error: string, attempts: Result["attempts"] } })?.RepairFailed;
+            if (failed) {
+                setResults((prevResults: Result[]) => [...prevResults, { err: "RepairFailed", msg: failed.error, data: null, sql: "", question: question, attempts: failed.attempts }]);
+                toast.error("Query still failing after " + failed.attempts?.length + " attempts: " + failed.error)
+                return;
+            }
+            showError(e)
+        } finally {
+            unlisten();
+            setRequestId(null);
         }
     }
 
@@ -100,9 +151,12 @@ const QueryComp = ({ setResults, connection }: {
             toast.error("Error: Please choose an AI model file before asking!")
             return;
         }
+        const requestId = crypto.randomUUID();
+        setRequestId(requestId);
+        const unlisten = await listenForTokens(requestId);
         try {
             const { invoke } = await import("@tauri-apps/api");
-            const res = await invoke<string>('ask_for_sql', { data: JSON.stringify(connection), question: question });
+            const res = await invoke<string>('ask_for_sql', { question: question, requestId: requestId });
             const json: Result = JSON.parse(res) as Result;
 
             setSql(json.sql);
@@ -111,9 +165,11 @@ const QueryComp = ({ setResults, connection }: {
             setLoading(false);
             toast.info("Success! Retrieved query statement.")
         } catch (e) {
-            const msg = Object.entries(e as { [key: string]: string }).map(([key, value]) => key + " : " + value).join(', ');
             setLoading(false);
-            toast.error(msg)
+            showError(e)
+        } finally {
+            unlisten();
+            setRequestId(null);
         }
     }
 
@@ -134,21 +190,24 @@ const QueryComp = ({ setResults, connection }: {
             toast.error("Error: Please enter a valid SELECT query. Updates are not allowed!")
             return;
         }
+        const requestId = crypto.randomUUID();
+        setRequestId(requestId);
         try {
             const { invoke } = await import("@tauri-apps/api");
-            const res = await invoke<string>('query', { data: JSON.stringify(connection), sql: sql });
+            const res = await invoke<string>('query', { data: JSON.stringify(connection), sql: sql, requestId: requestId });
             const json: Result = JSON.parse(res) as Result;
 
             setResults((prevResults: Result[]) => [...prevResults, json]);
             setLoading(false);
 
-            if (Array.isArray(json.data)) {
-                toast.info("Success! Retrieved " + json.data.length + " lines of data.")
+            if (json.data) {
+                toast.info("Success! Retrieved " + json.data.row_count + " lines of data in " + json.data.elapsed_ms + " ms.")
             }
         } catch (e) {
-            const msg = Object.entries(e as { [key: string]: string }).map(([key, value]) => key + " : " + value).join(', ');
             setLoading(false);
-            toast.error(msg)
+            showError(e)
+        } finally {
+            setRequestId(null);
         }
     }
 
@@ -166,10 +225,13 @@ const QueryComp = ({ setResults, connection }: {
                         </CardHeader>
                         <CardContent className="space-y-2">
                             <Textarea placeholder="Type your question here.." id="question" onChange={questionHandler} />
+                            {/* SQL being written by the model */}
+                            {loading && streamed && <pre className="text-xs text-muted-foreground whitespace-pre-wrap font-mono">{streamed}</pre>}
                         </CardContent>
                         <CardFooter className="gap-2">
                             <Button disabled={loading} onClick={ask}>Ask<Spinner show={loading} className="ml-2" size="xsmall" color="white"/></Button>
                             <Button disabled={loading} onClick={ask_for_sql}>Ask for SQL<Spinner show={loading} className="ml-2" size="xsmall" color="white"/></Button>
+                            {loading && requestId && <Button variant="outline" onClick={cancel}>Cancel</Button>}
                         </CardFooter>
                     </Card>
                 </TabsContent>
@@ -181,8 +243,9 @@ const QueryComp = ({ setResults, connection }: {
                         <CardContent className="space-y-2">
                             <Textarea placeholder="Your sql query goes here.." value={sql} onChange={sqlHandler} />
                         </CardContent>
-                        <CardFooter>
+                        <CardFooter className="gap-2">
                             <Button disabled={loading} onClick={query}>Query <Spinner show={loading} className="ml-2" size="xsmall" color="white"/></Button>
+                            {loading && requestId && <Button variant="outline" onClick={cancel}>Cancel</Button>}
                         </CardFooter>
                     </Card>
                 </TabsContent>
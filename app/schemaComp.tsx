@@ -0,0 +1,141 @@
+import React, { useState } from "react"
+
+import { Button } from "../components/ui/button"
+import {
+    Dialog,
+    DialogContent,
+    DialogTitle,
+    DialogTrigger,
+} from "../components/ui/dialog"
+import { ScrollArea } from "@/components/ui/scroll-area"
+import {
+    Table,
+    TableBody,
+    TableCell,
+    TableHead,
+    TableHeader,
+    TableRow,
+} from "@/components/ui/table"
+import { Config, SchemaTable, TableSummary } from "./useLocalStorage"
+import { toast } from "sonner"
+
+// The name of a table as written in queries
+const qualifiedName = (table: { schema: string | null, name: string }) =>
+    table.schema ? table.schema + "." + table.name : table.name;
+
+/**
+ * SchemaComp component lets users browse the tables the engine knows about,
+ * with their columns, keys, indexes and comments.
+ */
+const SchemaComp = ({ connection }: { connection: Config | null }) => {
+    const [tables, setTables] = useState<TableSummary[]>([]);
+    const [selected, setSelected] = useState<SchemaTable | null>(null);
+
+    /**
+     * Loads the list of tables when the dialog opens.
+     */
+    const listTables = async (open: boolean) => {
+        if (!open) {
+            setSelected(null);
+            return;
+        }
+        try {
+            const { invoke } = await import("@tauri-apps/api");
+            setTables(JSON.parse(await invoke<string>('list_tables')));
+        } catch (e) {
+            toast.error("Tables cannot be listed. " + JSON.stringify(e));
+        }
+    }
+
+    /**
+     * Loads the columns, keys and indexes of a table.
+     */
+    const describeTable = async (table: TableSummary) => {
+        try {
+            const { invoke } = await import("@tauri-apps/api");
+            setSelected(JSON.parse(await invoke<string>('describe_table', { name: table.name, schema: table.schema })));
+        } catch (e) {
+            toast.error("Table cannot be described. " + JSON.stringify(e));
+        }
+    }
+
+    if (!connection) {
+        return null;
+    }
+
+    return (
+        <Dialog onOpenChange={listTables}>
+            <DialogTrigger asChild>
+                <Button variant="outline">Schema</Button>
+            </DialogTrigger>
+            <DialogContent className="pb-6 gap-6 sm:max-w-[1000px]">
+                <DialogTitle>Schema</DialogTitle>
+                <div className="flex gap-6 h-[60vh]">
+                    {/* Tables and views of the database */}
+                    <ScrollArea className="w-1/3 border rounded-md">
+                        {tables.map((table) => (
+                            <div key={qualifiedName(table)} onClick={() => describeTable(table)} className="px-3 py-2 text-sm hover:bg-zinc-100 hover:cursor-pointer">
+                                <div className="font-mono">{qualifiedName(table)}</div>
+                                <div className="text-xs text-muted-foreground">
+                                    {table.kind == "Table" ? "" : (table.kind == "View" ? "view, " : "materialized view, ")}
+                                    {table.columns} columns
+                                    {table.row_estimate !== undefined && ", about " + table.row_estimate + " rows"}
+                                </div>
+                            </div>
+                        ))}
+                    </ScrollArea>
+
+                    {/* Columns, keys and indexes of the selected table */}
+                    <ScrollArea className="w-2/3">
+                        {selected && (
+                            <div className="text-sm">
+                                <div className="font-bold font-mono">{qualifiedName(selected)}</div>
+                                {selected.comment && <div className="text-muted-foreground">{selected.comment}</div>}
+                                <Table>
+                                    <TableHeader>
+                                        <TableRow>
+                                            <TableHead>Column</TableHead>
+                                            <TableHead>Type</TableHead>
+                                            <TableHead>Comment</TableHead>
+                                        </TableRow>
+                                    </TableHeader>
+                                    <TableBody>
+                                        {selected.columns.map((column) => (
+                                            <TableRow key={column.name}>
+                                                <TableCell className="font-mono">
+                                                    {column.name}
+                                                    {selected.primary_key.includes(column.name) && " (key)"}
+                                                </TableCell>
+                                                <TableCell className="font-mono">
+                                                    {column.data_type}
+                                                    {!column.nullable && " not null"}
+                                                    {column.generated && " generated"}
+                                                    {column.enum_values && column.enum_values.length > 0 && (
+                                                        <div className="text-xs text-muted-foreground">{column.enum_values.join(", ")}</div>
+                                                    )}
+                                                </TableCell>
+                                                <TableCell>{column.comment}</TableCell>
+                                            </TableRow>
+                                        ))}
+                                    </TableBody>
+                                </Table>
+                                {selected.foreign_keys.map((fk, index) => (
+                                    <div key={index} className="font-mono text-xs mt-2">
+                                        ({fk.columns.join(", ")}) references {qualifiedName({ schema: fk.referenced_schema, name: fk.referenced_table })}({fk.referenced_columns.join(", ")})
+                                    </div>
+                                ))}
+                                {selected.indexes.map((index) => (
+                                    <div key={index.name} className="font-mono text-xs mt-2">
+                                        {index.unique ? "unique index " : "index "}{index.name} ({index.columns.join(", ")})
+                                    </div>
+                                ))}
+                            </div>
+                        )}
+                    </ScrollArea>
+                </div>
+            </DialogContent>
+        </Dialog>
+    )
+}
+
+export default SchemaComp;
@@ -10,15 +10,99 @@ export interface Config {
     ai_cli_path: string;
     ai_model_path: string;
     sql_knowledge: string;
+    // PostgreSQL schemas or MySQL databases to describe to the model, empty for the default one
+    schemas?: string[];
+    // Show the model sample rows and values of the tables, on unless turned off
+    sample_data?: boolean;
+}
+
+// A generated query tried while answering a question, failed ones carry the database error
+export interface Attempt {
+    sql: string;
+    error: string | null;
+}
+
+// A column of a query result, `json` tells how its values are written
+export interface ResultColumn {
+    name: string;
+    db_type: string;
+    // null when the database cannot tell
+    nullable: boolean | null;
+    json: "boolean" | "integer" | "bigint" | "float" | "decimal" | "string" | "temporal" | "binary" | "json" | "array" | "mixed";
+    // The type has no decoder, values are its bytes as text or base64, see `LossyCell`
+    lossy: boolean;
+}
+
+// A value of a type without decoder and how it is written
+export interface LossyCell {
+    row: number;
+    column: number;
+    encoding: "text" | "base64";
+}
+
+// The rows of a query, one array of values per row in column order
+export interface ResultSet {
+    columns: ResultColumn[];
+    rows: unknown[][];
+    row_count: number;
+    elapsed_ms: number;
+    // Columns whose values could not be decoded faithfully
+    warnings?: string[];
+    lossy_cells?: LossyCell[];
 }
 
 // Define the Result type, representing the structure of a query result
 export interface Result {
     err: string;
     msg: string;
-    data: unknown;
+    data: ResultSet | null;
     sql: string;
     question: string;
+    attempts?: Attempt[];
+    // Tables described to the model for the question
+    tables?: string[];
+}
+
+// A table or view of the connected database as returned by `list_tables`
+export interface TableSummary {
+    // Schema or database of the table, null for the default one
+    schema: string | null;
+    name: string;
+    kind: "Table" | "View" | "MaterializedView";
+    comment?: string;
+    // Rows according to the database statistics, missing when unknown
+    row_estimate?: number;
+    columns: number;
+}
+
+export interface SchemaColumn {
+    name: string;
+    data_type: string;
+    nullable: boolean;
+    comment?: string;
+    enum_values?: string[];
+    generated: boolean;
+}
+
+export interface SchemaForeignKey {
+    columns: string[];
+    referenced_schema: string | null;
+    referenced_table: string;
+    referenced_columns: string[];
+}
+
+export interface SchemaIndex {
+    name: string;
+    columns: string[];
+    unique: boolean;
+}
+
+// A table as returned by `describe_table`, `get_schema` returns `{ tables: SchemaTable[] }`
+export interface SchemaTable extends Omit<TableSummary, "columns"> {
+    columns: SchemaColumn[];
+    primary_key: string[];
+    foreign_keys: SchemaForeignKey[];
+    indexes: SchemaIndex[];
 }
 
 // Utility function to get all Configs from Local Storage
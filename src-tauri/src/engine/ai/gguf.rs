@@ -0,0 +1,142 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::engine::errors::AppError;
+
+// GGUF metadata value types, see https://github.com/ggerganov/ggml/blob/master/docs/gguf.md
+const TYPE_UINT8: u32 = 0;
+const TYPE_INT8: u32 = 1;
+const TYPE_UINT16: u32 = 2;
+const TYPE_INT16: u32 = 3;
+const TYPE_UINT32: u32 = 4;
+const TYPE_INT32: u32 = 5;
+const TYPE_FLOAT32: u32 = 6;
+const TYPE_BOOL: u32 = 7;
+const TYPE_STRING: u32 = 8;
+const TYPE_ARRAY: u32 = 9;
+const TYPE_UINT64: u32 = 10;
+const TYPE_INT64: u32 = 11;
+const TYPE_FLOAT64: u32 = 12;
+
+/// Read a string value from the metadata of a GGUF model file, e.g. `tokenizer.chat_template`.
+/// Returns Ok(None) when the key is not present.
+pub fn read_string(path: &Path, key: &str) -> Result<Option<String>, AppError> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"GGUF" {
+        return Err(AppError::IOError(format!(
+            "{} is not a GGUF file",
+            path.display()
+        )));
+    }
+    let version = read_u32(&mut reader)?;
+    // Version 1 used 32 bit counts, later versions 64 bit
+    let (_tensor_count, kv_count) = if version == 1 {
+        (read_u32(&mut reader)? as u64, read_u32(&mut reader)? as u64)
+    } else {
+        (read_u64(&mut reader)?, read_u64(&mut reader)?)
+    };
+
+    for _ in 0..kv_count {
+        let name = read_string_value(&mut reader)?;
+        let value_type = read_u32(&mut reader)?;
+        if name == key && value_type == TYPE_STRING {
+            return Ok(Some(read_string_value(&mut reader)?));
+        }
+        skip_value(&mut reader, value_type)?;
+    }
+    Ok(None)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, AppError> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, AppError> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_string_value<R: Read>(reader: &mut R) -> Result<String, AppError> {
+    let len = read_u64(reader)?;
+    let mut buf = Vec::new();
+    reader.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(AppError::IOError("truncated GGUF metadata".to_string()));
+    }
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+// Skip over a value without reading it, token arrays can hold hundreds of thousands of entries
+fn skip_value(reader: &mut BufReader<File>, value_type: u32) -> Result<(), AppError> {
+    let size: i64 = match value_type {
+        TYPE_UINT8 | TYPE_INT8 | TYPE_BOOL => 1,
+        TYPE_UINT16 | TYPE_INT16 => 2,
+        TYPE_UINT32 | TYPE_INT32 | TYPE_FLOAT32 => 4,
+        TYPE_UINT64 | TYPE_INT64 | TYPE_FLOAT64 => 8,
+        TYPE_STRING => read_u64(reader)? as i64,
+        TYPE_ARRAY => {
+            let item_type = read_u32(reader)?;
+            let count = read_u64(reader)?;
+            for _ in 0..count {
+                skip_value(reader, item_type)?;
+            }
+            0
+        }
+        other => {
+            return Err(AppError::IOError(format!(
+                "unknown GGUF metadata type {}",
+                other
+            )))
+        }
+    };
+    reader.seek_relative(size)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn string(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as u64).to_le_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    #[test]
+    fn test_read_chat_template() {
+        let mut buf = b"GGUF".to_vec();
+        buf.extend_from_slice(&3u32.to_le_bytes());
+        buf.extend_from_slice(&0u64.to_le_bytes());
+        buf.extend_from_slice(&3u64.to_le_bytes());
+        string(&mut buf, "general.architecture");
+        buf.extend_from_slice(&TYPE_STRING.to_le_bytes());
+        string(&mut buf, "llama");
+        string(&mut buf, "tokenizer.ggml.tokens");
+        buf.extend_from_slice(&TYPE_ARRAY.to_le_bytes());
+        buf.extend_from_slice(&TYPE_STRING.to_le_bytes());
+        buf.extend_from_slice(&2u64.to_le_bytes());
+        string(&mut buf, "<s>");
+        string(&mut buf, "</s>");
+        string(&mut buf, "tokenizer.chat_template");
+        buf.extend_from_slice(&TYPE_STRING.to_le_bytes());
+        string(&mut buf, "{{ '<|im_start|>' }}");
+
+        let path = std::env::temp_dir().join("lookawry_test_model.gguf");
+        File::create(&path).unwrap().write_all(&buf).unwrap();
+
+        assert_eq!(
+            read_string(&path, "tokenizer.chat_template").unwrap(),
+            Some("{{ '<|im_start|>' }}".to_string())
+        );
+        assert_eq!(read_string(&path, "general.name").unwrap(), None);
+        let _ = std::fs::remove_file(path);
+    }
+}
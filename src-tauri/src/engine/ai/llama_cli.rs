@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use async_trait::async_trait;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+
+use super::{AiBackend, OnToken, Prompt, MAX_TOKENS, TEMPERATURE};
+use crate::engine::errors::AppError;
+
+/// Spawns the bundled `llama-cli` for every question, reloading the model each time
+pub struct LlamaCli {
+    cli_path: String,
+    model_path: String,
+}
+
+impl LlamaCli {
+    pub fn new(cli_path: &str, model_path: &str) -> Self {
+        LlamaCli {
+            cli_path: cli_path.to_string(),
+            model_path: model_path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl AiBackend for LlamaCli {
+    fn identity(&self) -> String {
+        format!("llama-cli {} {}", self.cli_path, self.model_path)
+    }
+
+    async fn start(&mut self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn complete(&mut self, prompt: &Prompt, on_token: &OnToken) -> Result<String, AppError> {
+        // Spawn the AI process using the config values
+        let mut command = Command::new(&self.cli_path);
+        let grammar_file = match &prompt.grammar {
+            Some(grammar) => Some(GrammarFile::write(grammar)?),
+            None => None,
+        };
+        if let Some(grammar_file) = &grammar_file {
+            command.arg("--grammar-file").arg(&grammar_file.0);
+        }
+        let mut child = command
+            .arg("-m")
+            .arg(&self.model_path)
+            .arg("-p")
+            .arg(prompt.render())
+            .arg("-n")
+            .arg(MAX_TOKENS.to_string())
+            .arg("--temp")
+            .arg(TEMPERATURE.to_string())
+            .arg("--no-display-prompt")
+            // A model with a chat template would otherwise start a conversation waiting on stdin
+            .arg("-no-cnv")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|_| AppError::EngineExecutionError("Ai engine cannot start.".to_string()))?;
+        let mut stdout = child.stdout.take().ok_or_else(|| {
+            AppError::EngineExecutionError("Ai engine has no output.".to_string())
+        })?;
+
+        // Pass on output as it is printed, holding back a UTF-8 sequence split between reads
+        let mut answer = String::new();
+        let mut pending: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            let read = stdout.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buf[..read]);
+            let complete = match std::str::from_utf8(&pending) {
+                Ok(text) => text.len(),
+                Err(e) => e.valid_up_to() + e.error_len().unwrap_or(0),
+            };
+            let text: String = String::from_utf8_lossy(&pending[..complete]).into_owned();
+            pending.drain(..complete);
+            if !text.is_empty() {
+                on_token(&text);
+                answer.push_str(&text);
+            }
+        }
+        answer.push_str(&String::from_utf8_lossy(&pending));
+
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(AppError::EngineExecutionError(format!(
+                "llama-cli exited with {}",
+                status
+            )));
+        }
+        Ok(answer)
+    }
+}
+
+// A grammar passed as a file, inline it can go past the command line limit of Windows. The
+// file is removed when the completion ends or is dropped.
+struct GrammarFile(PathBuf);
+
+impl GrammarFile {
+    fn write(grammar: &str) -> Result<Self, AppError> {
+        let path =
+            std::env::temp_dir().join(format!("lookawry-grammar-{}.gbnf", uuid::Uuid::new_v4()));
+        std::fs::write(&path, grammar)?;
+        Ok(GrammarFile(path))
+    }
+}
+
+impl Drop for GrammarFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grammar_file_is_removed() {
+        let file = GrammarFile::write("root ::= \"SELECT 1\"").unwrap();
+        let path = file.0.clone();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "root ::= \"SELECT 1\""
+        );
+        drop(file);
+        assert!(!path.exists());
+    }
+}
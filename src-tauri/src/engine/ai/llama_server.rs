@@ -0,0 +1,258 @@
+use std::net::TcpListener;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::process::{Child, Command};
+
+use super::{read_embeddings, read_events, AiBackend, OnToken, Prompt, MAX_TOKENS, TEMPERATURE};
+use crate::engine::errors::AppError;
+
+// Loading a large GGUF model on CPU can take a while
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(180);
+// An external server should already be up, only give it time to answer
+const EXTERNAL_TIMEOUT: Duration = Duration::from_secs(10);
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// One event of a streamed completion
+#[derive(Deserialize)]
+struct CompletionChunk {
+    #[serde(default)]
+    content: String,
+}
+
+/// A llama.cpp server spoken to over HTTP. Either a long-lived `llama-server` child process
+/// that keeps the model loaded between questions, or a server that is already running.
+pub struct LlamaServer {
+    // Binary and model of the managed child process, None for an external server
+    server_path: Option<String>,
+    model_path: String,
+    // Run the managed server in embedding mode, it then only answers `/v1/embeddings`
+    embeddings: bool,
+    base_url: String,
+    child: Option<Child>,
+    client: reqwest::Client,
+}
+
+impl LlamaServer {
+    pub fn new(server_path: &str, model_path: &str) -> Self {
+        LlamaServer {
+            server_path: Some(server_path.to_string()),
+            model_path: model_path.to_string(),
+            embeddings: false,
+            base_url: String::new(),
+            child: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // A second managed server computing embeddings with the same model
+    pub fn embeddings(server_path: &str, model_path: &str) -> Self {
+        LlamaServer {
+            embeddings: true,
+            ..LlamaServer::new(server_path, model_path)
+        }
+    }
+
+    // Use a llama.cpp server that is already running at `base_url`, e.g. `http://127.0.0.1:8080`
+    pub fn external(base_url: &str) -> Self {
+        LlamaServer {
+            server_path: None,
+            model_path: String::new(),
+            embeddings: false,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            child: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // Spawn the managed server on a free port
+    fn spawn(&mut self, server_path: &str) -> Result<(), AppError> {
+        let port = free_port()?;
+        let mut command = Command::new(server_path);
+        if self.embeddings {
+            command.args(["--embeddings", "--pooling", "mean"]);
+        }
+        let child = command
+            .arg("-m")
+            .arg(&self.model_path)
+            .arg("--host")
+            .arg("127.0.0.1")
+            .arg("--port")
+            .arg(port.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| AppError::ServerError(format!("llama-server cannot start: {}", e)))?;
+        self.child = Some(child);
+        self.base_url = format!("http://127.0.0.1:{}", port);
+        Ok(())
+    }
+
+    // Poll /health until the server answers 200, it returns 503 while the model is loading
+    async fn wait_until_healthy(&mut self) -> Result<(), AppError> {
+        let started = Instant::now();
+        let timeout = match self.server_path {
+            Some(_) => STARTUP_TIMEOUT,
+            None => EXTERNAL_TIMEOUT,
+        };
+        loop {
+            if let Some(status) = self.exit_status() {
+                self.child = None;
+                return Err(AppError::ServerError(format!(
+                    "llama-server exited during startup ({})",
+                    status
+                )));
+            }
+            if let Ok(res) = self.client.get(self.url("/health")).send().await {
+                if res.status().is_success() {
+                    return Ok(());
+                }
+            }
+            if started.elapsed() > timeout {
+                self.shutdown().await;
+                return Err(AppError::ServerError(format!(
+                    "llama-server at {} did not become ready in time",
+                    self.base_url
+                )));
+            }
+            tokio::time::sleep(HEALTH_POLL_INTERVAL).await;
+        }
+    }
+
+    // Restart the managed server if the child process has died since the last request
+    async fn ensure_running(&mut self) -> Result<(), AppError> {
+        if self.server_path.is_some() && (self.child.is_none() || self.exit_status().is_some()) {
+            self.start().await?;
+        }
+        Ok(())
+    }
+
+    fn exit_status(&mut self) -> Option<std::process::ExitStatus> {
+        match &mut self.child {
+            Some(child) => child.try_wait().ok().flatten(),
+            None => None,
+        }
+    }
+
+    // `streamed` tells whether any token was passed to `on_token`, even when it fails
+    async fn request_completion(
+        &self,
+        prompt: &Prompt,
+        on_token: &OnToken,
+        streamed: &mut bool,
+    ) -> Result<String, AppError> {
+        let mut body = json!({
+            "prompt": prompt.render(),
+            "n_predict": MAX_TOKENS,
+            "temperature": TEMPERATURE,
+            "stop": prompt.template.stop,
+            "cache_prompt": true,
+            "stream": true,
+        });
+        if let Some(grammar) = &prompt.grammar {
+            body["grammar"] = json!(grammar);
+        }
+        let res = self
+            .client
+            .post(self.url("/completion"))
+            .json(&body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+
+        let mut content = String::new();
+        read_events(res, |data| {
+            let chunk: CompletionChunk = serde_json::from_str(data)
+                .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+            if !chunk.content.is_empty() {
+                *streamed = true;
+                on_token(&chunk.content);
+                content.push_str(&chunk.content);
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(content)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+#[async_trait]
+impl AiBackend for LlamaServer {
+    fn identity(&self) -> String {
+        match &self.server_path {
+            Some(server_path) if self.embeddings => {
+                format!(
+                    "llama-server embeddings {} {}",
+                    server_path, self.model_path
+                )
+            }
+            Some(server_path) => format!("llama-server {} {}", server_path, self.model_path),
+            None => format!("llama-server {}", self.base_url),
+        }
+    }
+
+    /// Spawn the server if it is managed and wait until it reports the model is loaded
+    async fn start(&mut self) -> Result<(), AppError> {
+        self.shutdown().await;
+        if let Some(server_path) = self.server_path.clone() {
+            self.spawn(&server_path)?;
+        }
+        self.wait_until_healthy().await
+    }
+
+    /// Run a completion for the prompt and return the generated text
+    async fn complete(&mut self, prompt: &Prompt, on_token: &OnToken) -> Result<String, AppError> {
+        self.ensure_running().await?;
+        let mut streamed = false;
+        match self
+            .request_completion(prompt, on_token, &mut streamed)
+            .await
+        {
+            Ok(content) => Ok(content),
+            // The server may have crashed while handling the request, retry once on a fresh one.
+            // Not once tokens were streamed, the frontend would show them twice.
+            Err(_) if self.exit_status().is_some() && !streamed => {
+                self.start().await?;
+                self.request_completion(prompt, on_token, &mut streamed)
+                    .await
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Option<Vec<Vec<f32>>>, AppError> {
+        self.ensure_running().await?;
+        let res = self
+            .client
+            .post(self.url("/v1/embeddings"))
+            .json(&json!({ "input": texts }))
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+        Ok(Some(read_embeddings(res).await?))
+    }
+
+    /// Stop the server process if it is managed and running
+    async fn shutdown(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill().await;
+        }
+    }
+}
+
+// Ask the OS for an unused local port
+fn free_port() -> Result<u16, AppError> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
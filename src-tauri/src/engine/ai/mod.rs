@@ -0,0 +1,103 @@
+pub mod gguf;
+pub mod llama_cli;
+pub mod llama_server;
+pub mod openai;
+pub mod template;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::errors::AppError;
+use template::PromptTemplate;
+
+// Generation settings shared by every backend, SQL answers are short and should be deterministic
+pub const MAX_TOKENS: u32 = 128;
+pub const TEMPERATURE: f32 = 0.0;
+
+/// The parts of a question sent to the model
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub system: String,
+    pub user: String,
+    // Used by backends that take a raw prompt, chat APIs apply their own template
+    pub template: PromptTemplate,
+    // GBNF grammar the answer must follow, only llama.cpp backends support it
+    pub grammar: Option<String>,
+}
+
+impl Prompt {
+    pub fn render(&self) -> String {
+        self.template.render(self)
+    }
+}
+
+/// Callback receiving generated text as soon as the model produces it
+pub type OnToken = dyn Fn(&str) + Send + Sync;
+
+/// A way of running the language model
+#[async_trait]
+pub trait AiBackend: Send + Sync {
+    /// Identifies the model and process behind the backend, equal identities can be reused
+    fn identity(&self) -> String;
+
+    /// Prepare the backend, e.g. load the model or check the endpoint is reachable
+    async fn start(&mut self) -> Result<(), AppError>;
+
+    /// Generate an answer for the prompt, streaming pieces of it to `on_token`,
+    /// and return the whole answer
+    async fn complete(&mut self, prompt: &Prompt, on_token: &OnToken) -> Result<String, AppError>;
+
+    /// Embedding vectors for the texts, in order. None when the backend cannot compute them.
+    async fn embed(&mut self, _texts: &[String]) -> Result<Option<Vec<Vec<f32>>>, AppError> {
+        Ok(None)
+    }
+
+    /// Release processes held by the backend
+    async fn shutdown(&mut self) {}
+}
+
+// Answer of an OpenAI-compatible `/embeddings` request, llama-server speaks it too
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+// Read the vectors of an `/embeddings` answer in the order of the input texts
+async fn read_embeddings(res: reqwest::Response) -> Result<Vec<Vec<f32>>, AppError> {
+    let mut res: EmbeddingResponse = res
+        .json()
+        .await
+        .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+    res.data.sort_by_key(|data| data.index);
+    Ok(res.data.into_iter().map(|data| data.embedding).collect())
+}
+
+// Read a server-sent events stream and pass the payload of every `data:` line to `on_data`
+async fn read_events<F>(mut res: reqwest::Response, mut on_data: F) -> Result<(), AppError>
+where
+    F: FnMut(&str) -> Result<(), AppError> + Send,
+{
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .map_err(|e| AppError::EngineExecutionError(e.to_string()))?
+    {
+        buffer.extend_from_slice(&chunk);
+        // Splitting on the newline byte never cuts a UTF-8 sequence in half
+        while let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                on_data(data.trim_start())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{read_embeddings, read_events, AiBackend, OnToken, Prompt, MAX_TOKENS, TEMPERATURE};
+use crate::engine::errors::AppError;
+
+// One chunk of a streamed chat completion
+#[derive(Deserialize)]
+struct ChatChunk {
+    choices: Vec<ChunkChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    #[serde(default)]
+    content: Option<String>,
+}
+
+/// Any server speaking the OpenAI chat completions API, such as Ollama, LM Studio or vLLM
+/// running on localhost. `endpoint` is the API base URL, e.g. `http://localhost:11434/v1`.
+pub struct OpenAiBackend {
+    endpoint: String,
+    model: String,
+    api_key: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiBackend {
+    pub fn new(endpoint: &str, model: &str, api_key: &str) -> Self {
+        OpenAiBackend {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            model: model.to_string(),
+            api_key: api_key.to_string(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if self.api_key.is_empty() {
+            builder
+        } else {
+            builder.bearer_auth(&self.api_key)
+        }
+    }
+}
+
+#[async_trait]
+impl AiBackend for OpenAiBackend {
+    fn identity(&self) -> String {
+        format!("openai {} {}", self.endpoint, self.model)
+    }
+
+    // Check the endpoint is up before the first question
+    async fn start(&mut self) -> Result<(), AppError> {
+        self.request(self.client.get(format!("{}/models", self.endpoint)))
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| {
+                AppError::ServerError(format!("AI endpoint {} unreachable: {}", self.endpoint, e))
+            })?;
+        Ok(())
+    }
+
+    async fn complete(&mut self, prompt: &Prompt, on_token: &OnToken) -> Result<String, AppError> {
+        let body = json!({
+            "model": self.model,
+            "messages": [
+                { "role": "system", "content": prompt.system },
+                { "role": "user", "content": prompt.user },
+            ],
+            "max_tokens": MAX_TOKENS,
+            "temperature": TEMPERATURE,
+            "stream": true,
+        });
+        let res = self
+            .request(
+                self.client
+                    .post(format!("{}/chat/completions", self.endpoint)),
+            )
+            .json(&body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+
+        let mut answer = String::new();
+        read_events(res, |data| {
+            if data == "[DONE]" {
+                return Ok(());
+            }
+            let chunk: ChatChunk = serde_json::from_str(data)
+                .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+            for choice in chunk.choices {
+                if let Some(content) = choice.delta.content {
+                    on_token(&content);
+                    answer.push_str(&content);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(answer)
+    }
+
+    async fn embed(&mut self, texts: &[String]) -> Result<Option<Vec<Vec<f32>>>, AppError> {
+        let res = self
+            .request(self.client.post(format!("{}/embeddings", self.endpoint)))
+            .json(&json!({ "model": self.model, "input": texts }))
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+        Ok(Some(read_embeddings(res).await?))
+    }
+}
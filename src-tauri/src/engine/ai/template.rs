@@ -0,0 +1,201 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::gguf;
+use super::Prompt;
+use crate::engine::config::Config;
+use crate::engine::errors::AppError;
+
+/// A chat template for backends that take a raw prompt. `template` holds the whole prompt
+/// with `{system}` and `{user}` placeholders, `stop` the strings that end the answer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    #[serde(default)]
+    pub name: String,
+    pub template: String,
+    #[serde(default)]
+    pub stop: Vec<String>,
+}
+
+// Built-in presets as (name, template, stop)
+const PRESETS: &[(&str, &str, &[&str])] = &[
+    (
+        "phi3",
+        "<|system|>\n{system}<|end|>\n<|user|>\n{user}<|end|>\n<|assistant|>\n",
+        &["<|end|>"],
+    ),
+    (
+        "llama3",
+        "<|start_header_id|>system<|end_header_id|>\n\n{system}<|eot_id|>\
+         <|start_header_id|>user<|end_header_id|>\n\n{user}<|eot_id|>\
+         <|start_header_id|>assistant<|end_header_id|>\n\n",
+        &["<|eot_id|>"],
+    ),
+    ("mistral", "[INST] {system}\n\n{user} [/INST]", &["</s>"]),
+    (
+        "chatml",
+        "<|im_start|>system\n{system}<|im_end|>\n<|im_start|>user\n{user}<|im_end|>\n<|im_start|>assistant\n",
+        &["<|im_end|>"],
+    ),
+    (
+        "qwen",
+        "<|im_start|>system\n{system}<|im_end|>\n<|im_start|>user\n{user}<|im_end|>\n<|im_start|>assistant\n",
+        &["<|im_end|>", "<|endoftext|>"],
+    ),
+    (
+        "sqlcoder",
+        "### Task\nGenerate a SQL query to answer [QUESTION]{user}[/QUESTION]\n\n\
+         ### Database Schema\n{system}\n\n\
+         ### Answer\nGiven the database schema, here is the SQL query that answers \
+         [QUESTION]{user}[/QUESTION]\n[SQL]\n",
+        &["[/SQL]"],
+    ),
+];
+
+// The preset used when nothing else matches, the README recommends Phi-3
+const DEFAULT_PRESET: &str = "phi3";
+
+impl PromptTemplate {
+    /// Look up a built-in preset by name, case-insensitively
+    pub fn preset(name: &str) -> Option<PromptTemplate> {
+        let name = name.to_lowercase().replace(['-', '_', ' '], "");
+        PRESETS
+            .iter()
+            .find(|(preset, _, _)| *preset == name)
+            .map(|(preset, template, stop)| PromptTemplate {
+                name: preset.to_string(),
+                template: template.to_string(),
+                stop: stop.iter().map(|s| s.to_string()).collect(),
+            })
+    }
+
+    // Guess the preset from the Jinja chat template stored in the model
+    fn detect(chat_template: &str) -> Option<PromptTemplate> {
+        let name = if chat_template.contains("<|start_header_id|>") {
+            "llama3"
+        } else if chat_template.contains("<|im_start|>") {
+            "chatml"
+        } else if chat_template.contains("[INST]") {
+            "mistral"
+        } else if chat_template.contains("<|user|>") {
+            "phi3"
+        } else {
+            return None;
+        };
+        PromptTemplate::preset(name)
+    }
+
+    /// Render the prompt, placeholders are replaced in one pass so text inside the
+    /// knowledge or question is never substituted again
+    pub fn render(&self, prompt: &Prompt) -> String {
+        let mut rendered = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            rendered.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(after) = rest.strip_prefix("{system}") {
+                rendered.push_str(&prompt.system);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("{user}") {
+                rendered.push_str(&prompt.user);
+                rest = after;
+            } else {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        PromptTemplate::preset(DEFAULT_PRESET).expect("default preset exists")
+    }
+}
+
+/// Pick the prompt template for a config. `prompt_template` names a user template stored as
+/// `<data_dir>/templates/<name>.json` or a preset, user templates win so presets can be
+/// overridden. When it is empty the chat template in the GGUF metadata decides.
+pub fn resolve(config: &Config) -> Result<PromptTemplate, AppError> {
+    let name = config.prompt_template.trim();
+    if name.is_empty() {
+        return Ok(from_model(&config.ai_model_path).unwrap_or_default());
+    }
+
+    if let Some(template) = load_user_template(&config.data_dir, name)? {
+        return Ok(template);
+    }
+    PromptTemplate::preset(name)
+        .ok_or_else(|| AppError::ConfigError(format!("Unknown prompt template \"{}\"", name)))
+}
+
+fn from_model(model_path: &str) -> Option<PromptTemplate> {
+    if model_path.is_empty() {
+        return None;
+    }
+    let chat_template = gguf::read_string(Path::new(model_path), "tokenizer.chat_template")
+        .ok()
+        .flatten()?;
+    PromptTemplate::detect(&chat_template)
+}
+
+fn load_user_template(data_dir: &str, name: &str) -> Result<Option<PromptTemplate>, AppError> {
+    // The name becomes a file name, keep it from escaping the templates folder
+    if data_dir.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        return Ok(None);
+    }
+    let path = Path::new(data_dir)
+        .join("templates")
+        .join(format!("{}.json", name));
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let content = std::fs::read_to_string(&path)?;
+    let mut template: PromptTemplate = serde_json::from_str(&content).map_err(|e| {
+        AppError::ConfigError(format!("Invalid template {}: {}", path.display(), e))
+    })?;
+    template.name = name.to_string();
+    Ok(Some(template))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_presets() {
+        let prompt = Prompt {
+            system: "schema {user}".to_string(),
+            user: "how many students?".to_string(),
+            template: PromptTemplate::default(),
+            grammar: None,
+        };
+        assert_eq!(
+            PromptTemplate::preset("Phi-3").unwrap().render(&prompt),
+            "<|system|>\nschema {user}<|end|>\n<|user|>\nhow many students?<|end|>\n<|assistant|>\n"
+        );
+        let sqlcoder = PromptTemplate::preset("sqlcoder").unwrap().render(&prompt);
+        assert_eq!(sqlcoder.matches("how many students?").count(), 2);
+        for (name, _, _) in PRESETS {
+            let rendered = PromptTemplate::preset(name).unwrap().render(&prompt);
+            assert!(rendered.contains("schema {user}"), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_detect_from_chat_template() {
+        let llama3 = "{% for message in messages %}<|start_header_id|>{{ message['role'] }}";
+        assert_eq!(PromptTemplate::detect(llama3).unwrap().name, "llama3");
+        let qwen = "{% for message in messages %}{{'<|im_start|>' + message['role']}}";
+        assert_eq!(PromptTemplate::detect(qwen).unwrap().name, "chatml");
+        assert!(PromptTemplate::detect("{{ bos_token }}{{ messages }}").is_none());
+    }
+}
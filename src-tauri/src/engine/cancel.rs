@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio_util::sync::CancellationToken;
+
+/// Requests that are still running, by the id the frontend gave them. It is kept apart from
+/// the engine so a request can be cancelled while it holds the engine lock.
+#[derive(Default)]
+pub struct Cancellations {
+    // Tokens with the generation they were registered in, ids may be reused by the frontend
+    running: Mutex<HashMap<String, (u64, CancellationToken)>>,
+    generation: AtomicU64,
+}
+
+impl Cancellations {
+    /// Register a request, it can be cancelled until the returned guard is dropped.
+    /// Requests without an id get a token nobody can cancel.
+    pub fn register(self: &Arc<Self>, request_id: Option<String>) -> CancelGuard {
+        let token = CancellationToken::new();
+        let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+        if let Some(request_id) = &request_id {
+            self.lock()
+                .insert(request_id.clone(), (generation, token.clone()));
+        }
+        CancelGuard {
+            cancellations: self.clone(),
+            request_id,
+            generation,
+            token,
+        }
+    }
+
+    /// Cancel a running request, returns false when no request has this id
+    pub fn cancel(&self, request_id: &str) -> bool {
+        match self.lock().remove(request_id) {
+            Some((_, token)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, (u64, CancellationToken)>> {
+        // The map stays consistent even if a holder panicked
+        self.running.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Keeps a request registered while it runs
+pub struct CancelGuard {
+    cancellations: Arc<Cancellations>,
+    request_id: Option<String>,
+    generation: u64,
+    token: CancellationToken,
+}
+
+impl CancelGuard {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for CancelGuard {
+    fn drop(&mut self) {
+        if let Some(request_id) = &self.request_id {
+            let mut running = self.cancellations.lock();
+            // A later request may have registered under the same id
+            if matches!(running.get(request_id), Some((generation, _)) if *generation == self.generation)
+            {
+                running.remove(request_id);
+            }
+        }
+    }
+}
+
+/// Run `future` until it finishes or `cancel` fires, None when cancelled. The future is
+/// dropped on cancel, which stops a child process or closes a request it owns. A result that
+/// is ready wins over a cancellation.
+pub async fn until_cancelled<F: Future>(
+    cancel: &CancellationToken,
+    future: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        biased;
+        output = future => Some(output),
+        _ = cancel.cancelled() => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_running_request() {
+        let cancellations = Arc::new(Cancellations::default());
+        let guard = cancellations.register(Some("a".to_string()));
+        assert!(!cancellations.cancel("b"));
+        assert!(cancellations.cancel("a"));
+        assert!(guard.token().is_cancelled());
+
+        let guard = cancellations.register(Some("c".to_string()));
+        drop(guard);
+        assert!(!cancellations.cancel("c"));
+    }
+
+    #[test]
+    fn test_reused_id_stays_cancellable() {
+        let cancellations = Arc::new(Cancellations::default());
+        let first = cancellations.register(Some("a".to_string()));
+        let second = cancellations.register(Some("a".to_string()));
+        drop(first);
+        assert!(cancellations.cancel("a"));
+        assert!(second.token().is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_until_cancelled() {
+        let cancel = CancellationToken::new();
+        assert_eq!(until_cancelled(&cancel, async { 1 }).await, Some(1));
+        cancel.cancel();
+        assert_eq!(
+            until_cancelled(&cancel, std::future::pending::<()>()).await,
+            None
+        );
+    }
+}
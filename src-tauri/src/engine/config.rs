@@ -21,11 +21,74 @@ impl fmt::Display for DbType {
     }
 }
 
+// Which way of running the model to use, see `engine::ai`
+#[derive(Debug, Deserialize, Clone, Default)]
+pub enum AiBackendType {
+    LlamaCli,
+    // Falls back to llama-cli when no llama-server binary is bundled
+    #[default]
+    LlamaServer,
+    OpenAI,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+fn default_prompt_tables() -> usize {
+    10
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub db_type: DbType,
     pub connection_string: String,
     pub ai_cli_path: String,
     pub ai_model_path: String,
+    // Path of the bundled llama-server binary, resolved by the app like `ai_cli_path`
+    #[serde(default)]
+    pub ai_server_path: String,
+    #[serde(default)]
+    pub ai_backend: AiBackendType,
+    // Base URL of an already running server: a llama.cpp server for `LlamaServer`,
+    // or the OpenAI-compatible API root such as `http://localhost:11434/v1` for `OpenAI`
+    #[serde(default)]
+    pub ai_endpoint: String,
+    // Model name sent to an OpenAI-compatible endpoint
+    #[serde(default)]
+    pub ai_model_name: String,
+    #[serde(default)]
+    pub ai_api_key: String,
+    // Preset or user template name, empty to pick the template from the model metadata
+    #[serde(default)]
+    pub prompt_template: String,
+    // App data directory for user templates, resolved by the app
+    #[serde(default)]
+    pub data_dir: String,
+    // How many times a failing generated query is sent back to the model to be fixed, 0 disables it
+    #[serde(default)]
+    pub repair_attempts: u32,
+    // Constrain llama.cpp models with a grammar built from the tables described for the question
+    // so they can only write a query over them
+    #[serde(default = "enabled")]
+    pub sql_grammar: bool,
+    // PostgreSQL schemas or MySQL databases whose tables are described to the model,
+    // empty for `public` or the database of the connection string
+    #[serde(default)]
+    pub schemas: Vec<String>,
+    // Most tables described to the model per question, chosen by how well they match it,
+    // the tables they are joined with are added. 0 describes every table.
+    #[serde(default = "default_prompt_tables")]
+    pub prompt_tables: usize,
+    // Also rank tables by embedding similarity, computed with the model of the AI backend
+    #[serde(default)]
+    pub schema_embeddings: bool,
+    // Embedding model name sent to an OpenAI-compatible endpoint, chat models cannot embed.
+    // Without it tables are only ranked by their names and comments.
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    // Show the model sample rows and values of the tables it is given, off for sensitive data
+    #[serde(default = "enabled")]
+    pub sample_data: bool,
     pub sql_knowledge: String,
 }
@@ -1,7 +1,8 @@
 use serde::Serialize;
-use serde_json::json;
 use thiserror::Error;
 
+use super::Attempt;
+
 #[derive(Error, Debug, Serialize)]
 pub enum AppError {
     #[error("Failed to use config information: {0}")]
@@ -28,8 +29,17 @@ pub enum AppError {
     #[error("Execution error: {0}")]
     ExecutionError(String),
 
-    #[error("Unknown error")]
-    UnknownError,
+    #[error("Statement not allowed, {construct}: {reason}")]
+    StatementRejected { construct: String, reason: String },
+
+    #[error("Request was cancelled")]
+    Cancelled,
+
+    #[error("Query still failing after {} attempts: {error}", .attempts.len())]
+    RepairFailed {
+        error: String,
+        attempts: Vec<Attempt>,
+    },
 }
 
 // Implement From for std::io::Error so we can convert it to AppError::IOError
@@ -39,28 +49,14 @@ impl From<std::io::Error> for AppError {
     }
 }
 
-// Implement a method to convert the error into a JSON string
 impl AppError {
-    pub fn to_json(&self) -> String {
-        let (err, msg) = match self {
-            // Use `serde_json::json!` to create a JSON representation of the error
-            AppError::ConfigError(msg) => ("ConfigError", msg.clone()),
-            AppError::SqlReadError(msg) => ("SqlReadError", msg.clone()),
-            AppError::IOError(msg) => ("IOError", msg.clone()),
-            AppError::EngineExecutionError(msg) => ("EngineExecutionError", msg.clone()),
-            AppError::QueryError(msg) => ("QueryError", msg.clone()),
-            AppError::ServerError(msg) => ("ServerError", msg.clone()),
-            AppError::ConnectionError(msg) => ("QueryError", msg.clone()),
-            AppError::ExecutionError(msg) => ("ServerError", msg.clone()),
-            AppError::UnknownError => ("UnknownError", "An unknown error occurred.".to_string()),
-        };
-        let json: serde_json::Value = json!({
-            "err": err,
-            "msg": msg
-        });
-        json.to_string()
+    // Wrap the error message into another variant, structured errors are kept as they are
+    pub fn context(self, wrap: fn(String) -> AppError) -> AppError {
+        match self {
+            AppError::StatementRejected { .. }
+            | AppError::Cancelled
+            | AppError::RepairFailed { .. } => self,
+            _ => wrap(self.to_string()),
+        }
     }
 }
-
-// Alias for Result that uses AppError as the error type
-pub type Result<T> = std::result::Result<T, AppError>;
@@ -0,0 +1,247 @@
+use super::config::DbType;
+use super::schema::{quote_identifier, Schema};
+
+// Rules shared by every dialect. Only single SELECT statements with joins, subqueries,
+// grouping and ordering can be written. CTEs are left out because their names could not be
+// checked against the schema, derived tables cover the same queries.
+const COMMON_RULES: &str = r#"root ::= select-stmt ";"
+select-stmt ::= select-core (ws1 set-op ws1 select-core)* (ws1 order-by)? (ws1 limit)?
+set-op ::= "UNION" (ws1 "ALL")? | "INTERSECT" | "EXCEPT"
+select-core ::= "SELECT" (ws1 "DISTINCT")? ws1 select-list ws1 "FROM" ws1 from-list (ws1 "WHERE" ws1 expr)? (ws1 group-by)? (ws1 "HAVING" ws1 expr)?
+select-list ::= select-item (ws "," ws select-item)*
+select-item ::= "*" | alias "." "*" | expr (ws1 "AS" ws1 output-name)?
+from-list ::= table-item (ws1 join)* (ws "," ws table-item (ws1 join)*)*
+table-item ::= (table | "(" ws select-stmt ws ")") (ws1 ("AS" ws1)? alias)?
+join ::= (("LEFT" | "INNER") ws1)? "JOIN" ws1 table-item ws1 "ON" ws1 expr
+group-by ::= "GROUP BY" ws1 expr (ws "," ws expr)*
+order-by ::= "ORDER BY" ws1 order-item (ws "," ws order-item)*
+order-item ::= (expr | output-name) (ws1 ("ASC" | "DESC"))?
+limit ::= "LIMIT" ws1 integer (ws1 "OFFSET" ws1 integer)?
+expr ::= and-expr (ws1 "OR" ws1 and-expr)*
+and-expr ::= not-expr (ws1 "AND" ws1 not-expr)*
+not-expr ::= ("NOT" ws1)? predicate
+predicate ::= value (ws comparison)?
+comparison ::= compare-op ws value | ws1 "IS" ws1 ("NOT" ws1)? "NULL" | ws1 ("NOT" ws1)? "IN" ws "(" ws (select-stmt | value (ws "," ws value)*) ws ")" | ws1 ("NOT" ws1)? "LIKE" ws1 value | ws1 ("NOT" ws1)? "BETWEEN" ws1 value ws1 "AND" ws1 value
+compare-op ::= "=" | "<>" | "!=" | "<=" | ">=" | "<" | ">"
+value ::= term (ws arith-op ws term)*
+term ::= function | column-ref | literal | case | cast | "EXISTS" ws "(" ws select-stmt ws ")" | "(" ws (select-stmt | expr) ws ")"
+function ::= aggregate ws "(" ws (("DISTINCT" ws1)? expr | "*") ws ")" | scalar-function ws "(" ws (expr (ws "," ws expr)*)? ws ")"
+aggregate ::= "COUNT" | "SUM" | "AVG" | "MIN" | "MAX"
+case ::= "CASE" (ws1 "WHEN" ws1 expr ws1 "THEN" ws1 expr)+ (ws1 "ELSE" ws1 expr)? ws1 "END"
+cast ::= "CAST" ws "(" ws expr ws1 "AS" ws1 cast-type ws ")"
+column-ref ::= (alias ".")? column
+alias ::= table | [a-z] [a-z0-9_]? [a-z0-9_]?
+output-name ::= [a-z_] [a-z0-9_]*
+literal ::= number | string | "NULL" | "TRUE" | "FALSE"
+number ::= "-"? [0-9]+ ("." [0-9]+)?
+integer ::= [0-9]+
+string ::= "'" ([^'\\\n] | "''")* "'"
+ws ::= ws1?
+ws1 ::= " " | "\n" "  "?
+"#;
+
+/// GBNF grammar for llama.cpp that only lets the model write a read-only query for the
+/// dialect, using the table and column names of the schema. None when there is nothing to query.
+pub fn sql_grammar(db_type: &DbType, schema: &Schema) -> Option<String> {
+    let mut columns: Vec<&String> = schema
+        .tables
+        .iter()
+        .flat_map(|table| table.columns.iter().map(|column| &column.name))
+        .collect();
+    if columns.is_empty() {
+        return None;
+    }
+    columns.sort();
+    columns.dedup();
+
+    // `||` is a logical OR in MySQL, CONCAT is used there instead
+    let (arith_ops, functions, cast_types) = match db_type {
+        DbType::PostgreSQL => (
+            r#""+" | "-" | "*" | "/" | "%" | "||""#,
+            &[
+                "LOWER",
+                "UPPER",
+                "LENGTH",
+                "ROUND",
+                "ABS",
+                "COALESCE",
+                "NOW",
+                "DATE_TRUNC",
+                "TO_CHAR",
+                "CONCAT",
+            ][..],
+            &[
+                "INTEGER",
+                "BIGINT",
+                "NUMERIC",
+                "TEXT",
+                "DATE",
+                "TIMESTAMP",
+                "BOOLEAN",
+            ][..],
+        ),
+        DbType::MySQL => (
+            r#""+" | "-" | "*" | "/" | "%""#,
+            &[
+                "LOWER",
+                "UPPER",
+                "LENGTH",
+                "ROUND",
+                "ABS",
+                "COALESCE",
+                "NOW",
+                "DATE",
+                "YEAR",
+                "MONTH",
+                "DAY",
+                "DATE_FORMAT",
+                "CONCAT",
+            ][..],
+            &["SIGNED", "UNSIGNED", "DECIMAL", "CHAR", "DATE", "DATETIME"][..],
+        ),
+        DbType::SQLite => (
+            r#""+" | "-" | "*" | "/" | "%" | "||""#,
+            &[
+                "LOWER", "UPPER", "LENGTH", "ROUND", "ABS", "COALESCE", "DATE", "DATETIME",
+                "STRFTIME", "SUBSTR", "IFNULL",
+            ][..],
+            &["INTEGER", "REAL", "NUMERIC", "TEXT"][..],
+        ),
+    };
+
+    let mut grammar = COMMON_RULES.to_string();
+    grammar.push_str(&format!("arith-op ::= {}\n", arith_ops));
+    grammar.push_str(&format!(
+        "scalar-function ::= {}\n",
+        alternatives(functions.iter().map(|f| literal(f)))
+    ));
+    grammar.push_str(&format!(
+        "cast-type ::= {}\n",
+        alternatives(cast_types.iter().map(|t| literal(t)))
+    ));
+    grammar.push_str(&format!(
+        "table ::= {}\n",
+        alternatives(
+            schema
+                .tables
+                .iter()
+                .map(|table| literal(&table.qualified_name(db_type)))
+        )
+    ));
+    grammar.push_str(&format!(
+        "column ::= {}\n",
+        alternatives(
+            columns
+                .iter()
+                .map(|column| literal(&quote_identifier(column, db_type)))
+        )
+    ));
+    Some(grammar)
+}
+
+// A GBNF string literal matching `text` exactly
+fn literal(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn alternatives(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(" | ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::schema::{Column, Table};
+
+    fn schema() -> Schema {
+        let column = |name: &str| Column {
+            name: name.to_string(),
+            data_type: "integer".to_string(),
+            nullable: true,
+            ..Column::default()
+        };
+        Schema {
+            tables: vec![
+                Table {
+                    name: "students".to_string(),
+                    columns: vec![column("id"), column("name")],
+                    ..Table::default()
+                },
+                Table {
+                    name: "Grades".to_string(),
+                    columns: vec![column("student_id"), column("order")],
+                    ..Table::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_grammar_uses_schema_names() {
+        let grammar = sql_grammar(&DbType::PostgreSQL, &schema()).unwrap();
+        assert!(grammar.contains(r#"table ::= "students" | "\"Grades\"""#));
+        assert!(grammar.contains(r#"column ::= "id" | "name" | "\"order\"" | "student_id""#));
+        let grammar = sql_grammar(&DbType::MySQL, &schema()).unwrap();
+        assert!(grammar.contains(r#"table ::= "students" | "`Grades`""#));
+        assert!(sql_grammar(&DbType::SQLite, &Schema::default()).is_none());
+    }
+
+    #[test]
+    fn test_every_rule_is_defined() {
+        let grammar = sql_grammar(&DbType::SQLite, &schema()).unwrap();
+        let defined: Vec<&str> = grammar
+            .lines()
+            .filter_map(|line| line.split_once(" ::= ").map(|(name, _)| name))
+            .collect();
+        for line in grammar.lines() {
+            let (_, body) = line.split_once(" ::= ").unwrap();
+            // Drop string literals and character classes, the rest are rule names
+            let mut names = String::new();
+            let mut chars = body.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => {
+                        while let Some(c) = chars.next() {
+                            match c {
+                                '\\' => {
+                                    chars.next();
+                                }
+                                '"' => break,
+                                _ => {}
+                            }
+                        }
+                    }
+                    '[' => {
+                        while let Some(c) = chars.next() {
+                            match c {
+                                '\\' => {
+                                    chars.next();
+                                }
+                                ']' => break,
+                                _ => {}
+                            }
+                        }
+                    }
+                    c if c.is_ascii_alphanumeric() || c == '-' => names.push(c),
+                    _ => names.push(' '),
+                }
+            }
+            for name in names.split_whitespace() {
+                assert!(defined.contains(&name), "{} used in {:?}", name, line);
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+// Pick the tables worth describing to the model for a question. Large schemas do not fit in
+// the context of small local models, so tables are ranked by how well their names and columns
+// match the words of the question, optionally helped by embeddings, and only the best ones
+// plus the tables they are joined with through foreign keys end up in the prompt.
+
+use std::cmp::Ordering;
+
+use super::schema::{Schema, Table};
+
+// A question word naming a table counts more than one naming a column or found in a comment
+const TABLE_WEIGHT: f32 = 3.0;
+const COLUMN_WEIGHT: f32 = 1.0;
+// Cosine similarity is between -1 and 1, scaled to weigh about as much as a table name match
+const EMBEDDING_WEIGHT: f32 = 4.0;
+
+// Words used interchangeably in questions and in table names
+const SYNONYMS: &[&[&str]] = &[
+    &["customer", "client", "buyer"],
+    &["user", "account", "member"],
+    &["employee", "staff", "worker"],
+    &["product", "item", "article"],
+    &["order", "purchase"],
+    &["price", "cost"],
+    &["amount", "total", "sum"],
+    &["invoice", "bill"],
+    &["payment", "transaction"],
+    &["student", "pupil"],
+    &["grade", "score", "mark"],
+    &["date", "day", "time"],
+];
+
+/// The tables to describe for `question`: the `limit` best matching ones and the tables they
+/// reference or are referenced by, in schema order. `similarities` holds the embedding
+/// similarity of every table to the question when embeddings are used. Every table is kept
+/// when the schema is small enough or `limit` is 0.
+pub fn relevant_tables(
+    schema: &Schema,
+    question: &str,
+    limit: usize,
+    similarities: Option<&[f32]>,
+) -> Schema {
+    if limit == 0 || schema.tables.len() <= limit {
+        return schema.clone();
+    }
+
+    let words = expand(words(question));
+    let scores: Vec<f32> = schema
+        .tables
+        .iter()
+        .enumerate()
+        .map(|(i, table)| {
+            let similarity = similarities.and_then(|s| s.get(i)).copied().unwrap_or(0.0);
+            score(table, &words) + EMBEDDING_WEIGHT * similarity
+        })
+        .collect();
+
+    let mut ranked: Vec<usize> = (0..schema.tables.len()).collect();
+    ranked.sort_by(|a, b| by_score(&scores, *a, *b));
+    let mut chosen: Vec<usize> = ranked.into_iter().take(limit).collect();
+
+    // Neighbours are needed to write the joins, the best scoring ones come first
+    let mut neighbours: Vec<usize> = (0..schema.tables.len())
+        .filter(|i| !chosen.contains(i))
+        .filter(|i| chosen.iter().any(|c| joined(schema, *c, *i)))
+        .collect();
+    neighbours.sort_by(|a, b| by_score(&scores, *a, *b));
+    chosen.extend(neighbours.into_iter().take(limit));
+    chosen.sort_unstable();
+
+    Schema {
+        tables: chosen
+            .into_iter()
+            .map(|i| schema.tables[i].clone())
+            .collect(),
+    }
+}
+
+/// Text standing for a table when comparing it to a question with embeddings
+pub fn describe(table: &Table) -> String {
+    let columns: Vec<String> = table
+        .columns
+        .iter()
+        .map(|c| match &c.comment {
+            Some(comment) => format!("{} ({})", c.name, comment),
+            None => c.name.clone(),
+        })
+        .collect();
+    match &table.comment {
+        Some(comment) => format!("{} ({}): {}", table.name, comment, columns.join(", ")),
+        None => format!("{}: {}", table.name, columns.join(", ")),
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+// Highest score first, ties keep schema order as the sorts are stable
+fn by_score(scores: &[f32], a: usize, b: usize) -> Ordering {
+    scores[b].partial_cmp(&scores[a]).unwrap_or(Ordering::Equal)
+}
+
+fn score(table: &Table, words: &[String]) -> f32 {
+    let table_words = name_words(&table.name);
+    // Comments describe the table in other words than its names
+    let comments = table
+        .columns
+        .iter()
+        .filter_map(|column| column.comment.as_deref())
+        .chain(table.comment.as_deref());
+    let column_words: Vec<String> = table
+        .columns
+        .iter()
+        .flat_map(|column| name_words(&column.name))
+        .chain(comments.flat_map(self::words))
+        .collect();
+    words
+        .iter()
+        .map(|word| {
+            if table_words.contains(word) {
+                TABLE_WEIGHT
+            } else if column_words.contains(word) {
+                COLUMN_WEIGHT
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+// Whether one of the tables has a foreign key to the other
+fn joined(schema: &Schema, a: usize, b: usize) -> bool {
+    let references = |from: &Table, to: &Table| {
+        from.foreign_keys
+            .iter()
+            .any(|fk| fk.referenced_table == to.name && fk.referenced_schema == to.schema)
+    };
+    references(&schema.tables[a], &schema.tables[b])
+        || references(&schema.tables[b], &schema.tables[a])
+}
+
+// The distinct words of a question, lowercased and reduced to their singular form
+fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = Vec::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let word = singular(&word.to_lowercase());
+        if !words.contains(&word) {
+            words.push(word);
+        }
+    }
+    words
+}
+
+// The words of an identifier such as `order_items` or `OrderItems`
+fn name_words(name: &str) -> Vec<String> {
+    let mut spaced = String::new();
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() && previous_lower {
+            spaced.push(' ');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        spaced.push(c);
+    }
+    words(&spaced)
+}
+
+// Add the synonyms of every word
+fn expand(words: Vec<String>) -> Vec<String> {
+    let mut expanded = words.clone();
+    for word in &words {
+        for group in SYNONYMS
+            .iter()
+            .filter(|group| group.contains(&word.as_str()))
+        {
+            for synonym in group.iter().map(|s| s.to_string()) {
+                if !expanded.contains(&synonym) {
+                    expanded.push(synonym);
+                }
+            }
+        }
+    }
+    expanded
+}
+
+// Good enough English singular for matching table names, `categories` -> `category`
+fn singular(word: &str) -> String {
+    if word.len() > 4 && word.ends_with("ies") {
+        format!("{}y", &word[..word.len() - 3])
+    } else if word.len() > 4
+        && (word.ends_with("sses") || word.ends_with("xes") || word.ends_with("ches"))
+    {
+        word[..word.len() - 2].to_string()
+    } else if word.len() > 3 && word.ends_with('s') && !word.ends_with("ss") {
+        word[..word.len() - 1].to_string()
+    } else {
+        word.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::schema::{Column, ForeignKey};
+
+    fn table(name: &str, columns: &[&str], references: &[&str]) -> Table {
+        Table {
+            name: name.to_string(),
+            columns: columns
+                .iter()
+                .map(|c| Column {
+                    name: c.to_string(),
+                    data_type: "integer".to_string(),
+                    nullable: true,
+                    ..Column::default()
+                })
+                .collect(),
+            foreign_keys: references
+                .iter()
+                .map(|r| ForeignKey {
+                    columns: vec![format!("{}_id", r)],
+                    referenced_schema: None,
+                    referenced_table: r.to_string(),
+                    referenced_columns: vec!["id".to_string()],
+                })
+                .collect(),
+            ..Table::default()
+        }
+    }
+
+    fn names(schema: &Schema) -> Vec<&str> {
+        schema.tables.iter().map(|t| t.name.as_str()).collect()
+    }
+
+    fn shop() -> Schema {
+        Schema {
+            tables: vec![
+                table("audit_log", &["id", "message"], &[]),
+                table("categories", &["id", "title"], &[]),
+                table("clients", &["id", "name", "email"], &[]),
+                table("OrderItems", &["id", "quantity"], &["orders", "products"]),
+                table("orders", &["id", "created_at"], &["clients"]),
+                table("products", &["id", "price"], &["categories"]),
+                table("warehouses", &["id", "city"], &[]),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_ranks_by_names_and_adds_neighbours() {
+        let schema = shop();
+        // `customers` matches `clients` through its synonym, `orders` is a neighbour
+        let chosen = relevant_tables(&schema, "Which customers have an email?", 1, None);
+        assert_eq!(names(&chosen), vec!["clients", "orders"]);
+
+        let chosen = relevant_tables(&schema, "quantity of order items per category", 2, None);
+        assert_eq!(
+            names(&chosen),
+            vec!["categories", "OrderItems", "orders", "products"]
+        );
+    }
+
+    #[test]
+    fn test_small_schema_is_kept() {
+        let schema = shop();
+        assert_eq!(relevant_tables(&schema, "anything", 0, None), schema);
+        assert_eq!(relevant_tables(&schema, "anything", 7, None), schema);
+    }
+
+    #[test]
+    fn test_embeddings_break_ties() {
+        let schema = shop();
+        let mut similarities = vec![0.0; schema.tables.len()];
+        similarities[6] = 0.9;
+        let chosen = relevant_tables(&schema, "where do we store stock?", 1, Some(&similarities));
+        assert_eq!(names(&chosen), vec!["warehouses"]);
+    }
+}
@@ -1,180 +1,288 @@
-use std::str::FromStr;
+mod mysql;
+mod postgres;
+mod sqlite;
 
-use bigdecimal::BigDecimal;
-use rust_decimal::Decimal;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use sqlx::error::Error;
-use sqlx::{mysql::MySqlRow, postgres::PgRow, sqlite::SqliteRow, Column, Row, TypeInfo}; // Use Decimal from rust_decimal crate
-
-pub struct SQLiteParser;
-
-impl SQLiteParser {
-    pub async fn json(row: &SqliteRow) -> Result<Value, Error> {
-        let mut json_object = serde_json::Map::new();
-
-        for column in row.columns() {
-            let column_name = column.name();
-            let column_type = column.type_info().name();
-
-            let value = match column_type {
-                "INTEGER" => {
-                    let val: i64 = row.try_get(column_name)?;
-                    Value::Number(val.into())
-                }
-                "TEXT" => {
-                    let val: String = row.try_get(column_name)?;
-                    Value::String(val)
-                }
-                "REAL" => {
-                    let val: f64 = row.try_get(column_name)?;
-                    Value::Number(serde_json::Number::from_f64(val).unwrap())
-                }
-                "BOOLEAN" => {
-                    let val: bool = row.try_get(column_name)?;
-                    Value::Bool(val)
-                }
-                "DATE" => {
-                    let val: Option<chrono::NaiveDate> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::String(v.to_string()))
-                }
-                "DATETIME" => {
-                    let val: String = row.try_get(column_name)?;
-                    Value::String(val.to_string())
-                }
-                _ => Value::Null,
-            };
-            json_object.insert(column_name.to_string(), value);
-        }
+use sqlx::{Column, Database, TypeInfo};
+
+pub use mysql::MySQLParser;
+pub use postgres::PostgresParser;
+pub use sqlite::SQLiteParser;
+
+/// The rows returned by a query and the description of its columns
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultSet {
+    pub columns: Vec<ResultColumn>,
+    // One array per row with the values in column order
+    pub rows: Vec<Vec<Value>>,
+    pub row_count: usize,
+    // Time spent running the query on the database
+    pub elapsed_ms: u64,
+    // Columns whose values could not be decoded faithfully
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    // Every value of a type without decoder, with the way it is written
+    #[serde(default)]
+    pub lossy_cells: Vec<LossyCell>,
+}
 
-        Ok(Value::Object(json_object))
+impl ResultSet {
+    pub fn new(columns: Vec<ResultColumn>, rows: Vec<DecodedRow>, elapsed_ms: u64) -> Self {
+        let mut lossy_cells = Vec::new();
+        for (row, decoded) in rows.iter().enumerate() {
+            for &(column, encoding) in &decoded.fallbacks {
+                lossy_cells.push(LossyCell {
+                    row,
+                    column,
+                    encoding,
+                });
+            }
+        }
+        // Only worth a warning when there is a value to misread
+        let warnings = columns
+            .iter()
+            .enumerate()
+            .filter_map(|(i, column)| {
+                let written = |encoding| {
+                    lossy_cells
+                        .iter()
+                        .any(|cell| cell.column == i && cell.encoding == encoding)
+                };
+                let shown = match (written(Fallback::Text), written(Fallback::Base64)) {
+                    (false, false) => return None,
+                    (true, false) => "text",
+                    (false, true) => "base64 bytes",
+                    (true, true) => "text or base64 bytes, see the lossy cells",
+                };
+                Some(format!(
+                    "column {} has the unsupported type {}, its values are shown as {}",
+                    column.name, column.db_type, shown
+                ))
+            })
+            .collect();
+        ResultSet {
+            columns,
+            row_count: rows.len(),
+            rows: rows.into_iter().map(|row| row.values).collect(),
+            elapsed_ms,
+            warnings,
+            lossy_cells,
+        }
     }
 }
 
-pub struct PostgresParser;
-
-impl PostgresParser {
-    pub async fn json(row: &PgRow) -> Result<Value, Error> {
-        let mut json_object = serde_json::Map::new();
-
-        for column in row.columns() {
-            let column_name = column.name();
-            let column_type = column.type_info().name();
-
-            let value = match column_type {
-                "INT4" => {
-                    let val: Option<i32> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::Number(v.into()))
-                }
-                "INT2" => {
-                    let val: Option<i16> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::Number(v.into()))
-                }
-                "INT8" => {
-                    let val: Option<i64> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::Number(v.into()))
-                }
-                "VARCHAR" | "TEXT" | "CHAR" | "NAME" => {
-                    let val: Option<String> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, Value::String)
-                }
-                "FLOAT4" | "FLOAT8" => {
-                    let val: Option<f64> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| {
-                        Value::Number(serde_json::Number::from_f64(v).unwrap())
-                    })
-                }
-                "BOOL" => {
-                    let val: Option<bool> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, Value::Bool)
-                }
-                "TIMESTAMPTZ" | "TIMESTAMP" => {
-                    let val: Option<String> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, Value::String)
-                }
-                "DATE" => {
-                    let val: Option<chrono::NaiveDate> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::String(v.to_string()))
-                }
-                "UUID" => {
-                    let val: Option<String> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, Value::String)
-                }
-                "JSONB" => {
-                    let val: Option<serde_json::Value> = row.try_get(column_name)?;
-                    val.unwrap_or(Value::Null)
-                }
-                "BYTEA" => {
-                    let val: Option<Vec<u8>> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::String(base64::encode(v)))
-                }
-                "NUMERIC" => {
-                    let val: Option<i64> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| Value::Number(v.into()))
-                }
-                "ARRAY" => {
-                    let val: Option<Vec<String>> = row.try_get(column_name)?;
-                    val.map_or(Value::Null, |v| {
-                        Value::Array(v.into_iter().map(Value::String).collect())
-                    })
-                }
-                _ => Value::Null,
-            };
-
-            json_object.insert(column_name.to_string(), value);
+/// The values of a row in column order, with the columns whose value has a type without
+/// decoder
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecodedRow {
+    pub values: Vec<Value>,
+    pub fallbacks: Vec<(usize, Fallback)>,
+}
+
+/// A value of a type without decoder, by its row and column
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LossyCell {
+    pub row: usize,
+    pub column: usize,
+    pub encoding: Fallback,
+}
+
+/// How a value of a type without decoder is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fallback {
+    Text,
+    Base64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResultColumn {
+    pub name: String,
+    // Type name as reported by the database, for SQLite the declared type, empty for expressions
+    pub db_type: String,
+    // None when the database cannot tell, such as for SQLite or computed columns
+    pub nullable: Option<bool>,
+    pub json: JsonKind,
+    // The type has no decoder, values are its bytes as text or base64, see `LossyCell`
+    #[serde(default)]
+    pub lossy: bool,
+}
+
+/// How the values of a column are written in JSON, nulls aside
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonKind {
+    Boolean,
+    // An integer of at most 32 bits, as a number
+    Integer,
+    // A 64-bit integer, always as a string since JavaScript numbers only hold 2^53 exactly
+    BigInt,
+    // A number, or "NaN", "Infinity" and "-Infinity" as strings
+    Float,
+    // An exact decimal number as a string
+    Decimal,
+    String,
+    // Dates, times, timestamps and intervals as ISO 8601 strings
+    Temporal,
+    // Base64 string
+    Binary,
+    // Any JSON value as stored
+    Json,
+    Array,
+    // Depends on the value, as for SQLite columns without a type affinity
+    Mixed,
+}
+
+/// Describe `columns` with the JSON representation `kind` gives for their type, None when
+/// the parser has no decoder for it
+pub fn columns<C: Column>(
+    columns: &[C],
+    nullable: impl Fn(usize) -> Option<bool>,
+    kind: fn(&<C::Database as Database>::TypeInfo) -> Option<JsonKind>,
+) -> Vec<ResultColumn> {
+    columns
+        .iter()
+        .map(|column| {
+            let json = kind(column.type_info());
+            ResultColumn {
+                name: column.name().to_string(),
+                db_type: column.type_info().name().to_string(),
+                nullable: nullable(column.ordinal()),
+                json: json.unwrap_or(JsonKind::String),
+                lossy: json.is_none(),
+            }
+        })
+        .collect()
+}
+
+// A 64-bit integer as exact text. Small values are text too, so a column has one JSON type
+// whatever its values, see `JsonKind::BigInt`.
+fn big_integer(value: impl ToString) -> Value {
+    Value::String(value.to_string())
+}
+
+// Value of a type without decoder. Types sent as text, such as those of extensions, stay text
+// and other bytes are base64 so nothing is lost. Control characters mean a binary format whose
+// bytes happen to be valid UTF-8, such as a record.
+fn unsupported(bytes: &[u8]) -> (Value, Fallback) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => {
+            (Value::String(text.to_string()), Fallback::Text)
         }
+        _ => (Value::String(STANDARD.encode(bytes)), Fallback::Base64),
+    }
+}
 
-        Ok(Value::Object(json_object))
+// JSON has no NaN or infinity, they are kept as text the way PostgreSQL writes them. Single
+// precision values are printed as their shortest representation, so `0.1` stays `0.1`.
+fn float(value: f64, single: bool) -> Value {
+    if value.is_nan() {
+        return Value::String("NaN".to_string());
     }
+    if value.is_infinite() {
+        let text = if value > 0.0 { "Infinity" } else { "-Infinity" };
+        return Value::String(text.to_string());
+    }
+    let value = if single {
+        (value as f32).to_string().parse().unwrap_or(value)
+    } else {
+        value
+    };
+    serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
 }
 
-pub struct MySQLParser;
-
-impl MySQLParser {
-    pub async fn json(row: &MySqlRow) -> Result<Value, Error> {
-        let mut json_object = serde_json::Map::new();
-
-        for column in row.columns() {
-            let column_name = column.name();
-            let column_type = column.type_info().name();
-            let value = match column_type {
-                "INT" => {
-                    let val: Option<i32> = row.try_get(column_name)?; // Handle NULL as Option<i32>
-                    val.map_or(Value::Null, |v| Value::Number(v.into()))
-                }
-                "VARCHAR" | "TEXT" | "ENUM" => {
-                    let val: Option<String> = row.try_get(column_name)?; // Handle NULL as Option<String>
-                    val.map_or(Value::Null, Value::String)
-                }
-                "FLOAT" | "DOUBLE" => {
-                    let val: Option<f64> = row.try_get(column_name)?; // Handle NULL as Option<f64>
-                    val.map_or(Value::Null, |v| {
-                        Value::Number(serde_json::Number::from_f64(v).unwrap())
-                    })
-                }
-                "DECIMAL" => {
-                    let val: Option<Decimal> = row.try_get(column_name)?; // Handle NULL as Option<f64>
-                    val.map_or(Value::Null, |v| {
-                        Value::String(serde_json::to_string(&v).unwrap())
-                    })
-                }
-                "BOOL" => {
-                    let val: Option<bool> = row.try_get(column_name)?; // Handle NULL as Option<bool>
-                    val.map_or(Value::Null, Value::Bool)
-                }
-                "DATETIME" => {
-                    let val: Option<String> = row.try_get(column_name)?; // Handle NULL as Option<String>
-                    val.map_or(Value::Null, Value::String)
-                }
-                "DATE" => {
-                    let val: Option<chrono::NaiveDate> = row.try_get(column_name)?; // Handle NULL as Option<chrono::NaiveDate>
-                    val.map_or(Value::Null, |v| Value::String(v.to_string()))
-                }
-                _ => Value::Null,
-            };
-
-            json_object.insert(column_name.to_string(), value);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(name: &str, db_type: &str, lossy: bool) -> ResultColumn {
+        ResultColumn {
+            name: name.to_string(),
+            db_type: db_type.to_string(),
+            nullable: None,
+            json: JsonKind::String,
+            lossy,
         }
+    }
+
+    #[test]
+    fn test_numbers_keep_their_precision() {
+        assert_eq!(big_integer(7), json!("7"));
+        assert_eq!(big_integer(i64::MIN), json!("-9223372036854775808"));
+        assert_eq!(big_integer(u64::MAX), json!("18446744073709551615"));
+        assert_eq!(float(f64::NAN, false), json!("NaN"));
+        assert_eq!(float(f64::NEG_INFINITY, false), json!("-Infinity"));
+        assert_eq!(float(f64::from(0.1f32), true), json!(0.1));
+    }
+
+    #[test]
+    fn test_unsupported_values_are_text_or_bytes() {
+        assert_eq!(
+            unsupported(b"high\tlow"),
+            (json!("high\tlow"), Fallback::Text)
+        );
+        // The binary format of ROW(1, 2) is valid UTF-8 but not text
+        assert_eq!(
+            unsupported(&[0, 0, 0, 2, 0, 0, 0, 23]),
+            (json!("AAAAAgAAABc="), Fallback::Base64)
+        );
+        assert_eq!(unsupported(&[0xff]), (json!("/w=="), Fallback::Base64));
+    }
 
-        Ok(Value::Object(json_object))
+    #[test]
+    fn test_lossy_cells_and_warnings() {
+        let columns = vec![
+            column("id", "INT4", false),
+            column("shape", "POLYGON", true),
+            column("label", "ltree", true),
+            column("area", "BOX", true),
+        ];
+        let rows = vec![
+            DecodedRow {
+                values: vec![json!(1), json!("AAAA"), json!("a.b"), json!(null)],
+                fallbacks: vec![(1, Fallback::Base64), (2, Fallback::Text)],
+            },
+            DecodedRow {
+                values: vec![json!(2), json!("x"), json!(null), json!(null)],
+                fallbacks: vec![(1, Fallback::Text)],
+            },
+        ];
+        let result = ResultSet::new(columns, rows, 3);
+        assert_eq!(result.row_count, 2);
+        assert_eq!(
+            result.rows[1],
+            vec![json!(2), json!("x"), json!(null), json!(null)]
+        );
+        assert_eq!(
+            result.lossy_cells,
+            vec![
+                LossyCell {
+                    row: 0,
+                    column: 1,
+                    encoding: Fallback::Base64
+                },
+                LossyCell {
+                    row: 0,
+                    column: 2,
+                    encoding: Fallback::Text
+                },
+                LossyCell {
+                    row: 1,
+                    column: 1,
+                    encoding: Fallback::Text
+                },
+            ]
+        );
+        assert_eq!(
+            result.warnings,
+            vec![
+                "column shape has the unsupported type POLYGON, its values are shown as text or base64 bytes, see the lossy cells",
+                "column label has the unsupported type ltree, its values are shown as text",
+            ]
+        );
     }
 }
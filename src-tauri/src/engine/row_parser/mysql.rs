@@ -0,0 +1,340 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde_json::Value;
+use sqlx::error::{BoxDynError, Error};
+use sqlx::mysql::{MySqlRow, MySqlTypeInfo};
+use sqlx::{Column, Row, TypeInfo, ValueRef};
+
+use super::{big_integer, float, unsupported, DecodedRow, JsonKind};
+
+pub struct MySQLParser;
+
+impl MySQLParser {
+    /// Convert a row to its JSON values in column order. `sqlx::query` runs prepared statements,
+    /// so values come in the binary protocol of MySQL and are read from their bytes by type name.
+    pub async fn values(row: &MySqlRow) -> Result<DecodedRow, Error> {
+        let mut decoded_row = DecodedRow::default();
+
+        for column in row.columns() {
+            let column_name = column.name();
+            let raw = row.try_get_raw(column.ordinal())?;
+            let value = if raw.is_null() {
+                Value::Null
+            } else {
+                let type_info = raw.type_info().into_owned();
+                // The bytes of the value as sent, whatever its type
+                let bytes: &[u8] = row.try_get_unchecked(column.ordinal())?;
+                match Self::kind(&type_info) {
+                    Some(_) => {
+                        decode(type_info.name(), bytes).map_err(|source| Error::ColumnDecode {
+                            index: column_name.to_string(),
+                            source,
+                        })?
+                    }
+                    None => {
+                        let (value, fallback) = unsupported(bytes);
+                        decoded_row.fallbacks.push((column.ordinal(), fallback));
+                        value
+                    }
+                }
+            };
+            decoded_row.values.push(value);
+        }
+
+        Ok(decoded_row)
+    }
+
+    /// How `decode` writes values of the type, None when it has no decoder for it
+    pub fn kind(type_info: &MySqlTypeInfo) -> Option<JsonKind> {
+        let kind = match type_info.name() {
+            "BOOLEAN" => JsonKind::Boolean,
+            "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "TINYINT UNSIGNED"
+            | "SMALLINT UNSIGNED" | "MEDIUMINT UNSIGNED" | "INT UNSIGNED" | "YEAR" => {
+                JsonKind::Integer
+            }
+            "BIGINT" | "BIGINT UNSIGNED" | "BIT" => JsonKind::BigInt,
+            "FLOAT" | "DOUBLE" => JsonKind::Float,
+            "DECIMAL" => JsonKind::Decimal,
+            "DATE" | "DATETIME" | "TIMESTAMP" | "TIME" => JsonKind::Temporal,
+            "JSON" => JsonKind::Json,
+            "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM"
+            | "SET" => JsonKind::String,
+            "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB"
+            | "GEOMETRY" => JsonKind::Binary,
+            // Only ever NULL
+            "NULL" => JsonKind::Mixed,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// Decode a value of the named type from the binary protocol, integers are little endian
+fn decode(type_name: &str, bytes: &[u8]) -> Result<Value, BoxDynError> {
+    let value =
+        match type_name {
+            // TINYINT(1), usually a flag but it can hold other numbers
+            "BOOLEAN" => match signed(bytes, 1)? {
+                0 => Value::Bool(false),
+                1 => Value::Bool(true),
+                v => Value::from(v),
+            },
+            "TINYINT" => Value::from(signed(bytes, 1)?),
+            "SMALLINT" => Value::from(signed(bytes, 2)?),
+            "MEDIUMINT" | "INT" => Value::from(signed(bytes, 4)?),
+            "BIGINT" => big_integer(signed(bytes, 8)?),
+            "TINYINT UNSIGNED" => Value::from(unsigned(bytes, 1)?),
+            "SMALLINT UNSIGNED" | "YEAR" => Value::from(unsigned(bytes, 2)?),
+            "MEDIUMINT UNSIGNED" | "INT UNSIGNED" => Value::from(unsigned(bytes, 4)?),
+            "BIGINT UNSIGNED" => big_integer(unsigned(bytes, 8)?),
+            "FLOAT" => float(f64::from(f32::from_bits(unsigned(bytes, 4)? as u32)), true),
+            "DOUBLE" => float(f64::from_bits(unsigned(bytes, 8)?), false),
+            // Sent as text, kept as text so no digit is lost
+            "DECIMAL" => Value::String(std::str::from_utf8(bytes)?.to_string()),
+            // Up to 64 bits, most significant byte first
+            "BIT" => {
+                if bytes.len() > 8 {
+                    return Err(format!("BIT value of {} bytes", bytes.len()).into());
+                }
+                big_integer(bytes.iter().fold(0u64, |v, b| v << 8 | u64::from(*b)))
+            }
+            "DATE" => Value::String(date(bytes)?),
+            "DATETIME" | "TIMESTAMP" => Value::String(datetime(bytes)?),
+            "TIME" => Value::String(time(bytes)?),
+            "JSON" => serde_json::from_slice(bytes)?,
+            "CHAR" | "VARCHAR" | "TINYTEXT" | "TEXT" | "MEDIUMTEXT" | "LONGTEXT" | "ENUM"
+            | "SET" => Value::String(std::str::from_utf8(bytes)?.to_string()),
+            // GEOMETRY is the SRID followed by the WKB of the shape
+            "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB"
+            | "GEOMETRY" => Value::String(STANDARD.encode(bytes)),
+            "NULL" => Value::Null,
+            // Kept as they are by `MySQLParser::values`, see `MySQLParser::kind`
+            _ => return Err(format!("no decoder for {}", type_name).into()),
+        };
+    Ok(value)
+}
+
+fn unsigned(bytes: &[u8], len: usize) -> Result<u64, BoxDynError> {
+    if bytes.len() != len {
+        return Err(format!("expected {} bytes, got {}", len, bytes.len()).into());
+    }
+    Ok(bytes.iter().rev().fold(0u64, |v, b| v << 8 | u64::from(*b)))
+}
+
+// Sign extended from the width of the type
+fn signed(bytes: &[u8], len: usize) -> Result<i64, BoxDynError> {
+    let shift = 64 - 8 * len as u32;
+    Ok(((unsigned(bytes, len)? << shift) as i64) >> shift)
+}
+
+// The length of the value comes first, fields left out are zero. Zero dates such as
+// `0000-00-00` are valid in MySQL, so the parts are written out as they are.
+fn date(bytes: &[u8]) -> Result<String, BoxDynError> {
+    let (year, month, day) = match bytes {
+        [0] => (0, 0, 0),
+        [4..=11, y0, y1, month, day, ..] => (u16::from_le_bytes([*y0, *y1]), *month, *day),
+        _ => return Err(format!("invalid date of {} bytes", bytes.len()).into()),
+    };
+    Ok(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+fn datetime(bytes: &[u8]) -> Result<String, BoxDynError> {
+    let date = date(bytes)?;
+    let (hour, minute, second, micros) = match bytes {
+        [0] | [4, ..] => (0, 0, 0, 0),
+        [7, _, _, _, _, hour, minute, second] => (*hour, *minute, *second, 0),
+        [11, _, _, _, _, hour, minute, second, m0, m1, m2, m3] => (
+            *hour,
+            *minute,
+            *second,
+            u32::from_le_bytes([*m0, *m1, *m2, *m3]),
+        ),
+        _ => return Err(format!("invalid datetime of {} bytes", bytes.len()).into()),
+    };
+    Ok(format!(
+        "{}T{:02}:{:02}:{:02}{}",
+        date,
+        hour,
+        minute,
+        second,
+        fraction(micros)
+    ))
+}
+
+// A duration of up to 838 hours either way, as MySQL writes it
+fn time(bytes: &[u8]) -> Result<String, BoxDynError> {
+    let (negative, days, hour, minute, second, micros) = match bytes {
+        [0] => (0, 0, 0, 0, 0, 0),
+        [8, negative, d0, d1, d2, d3, hour, minute, second] => (
+            *negative,
+            u32::from_le_bytes([*d0, *d1, *d2, *d3]),
+            *hour,
+            *minute,
+            *second,
+            0,
+        ),
+        [12, negative, d0, d1, d2, d3, hour, minute, second, m0, m1, m2, m3] => (
+            *negative,
+            u32::from_le_bytes([*d0, *d1, *d2, *d3]),
+            *hour,
+            *minute,
+            *second,
+            u32::from_le_bytes([*m0, *m1, *m2, *m3]),
+        ),
+        _ => return Err(format!("invalid time of {} bytes", bytes.len()).into()),
+    };
+    Ok(format!(
+        "{}{:02}:{:02}:{:02}{}",
+        if negative != 0 { "-" } else { "" },
+        u64::from(days) * 24 + u64::from(hour),
+        minute,
+        second,
+        fraction(micros)
+    ))
+}
+
+// Fractional seconds in milliseconds or microseconds, nothing for whole seconds
+fn fraction(micros: u32) -> String {
+    match micros {
+        0 => String::new(),
+        _ if micros % 1000 == 0 => format!(".{:03}", micros / 1000),
+        _ => format!(".{:06}", micros),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decoded(type_name: &str, bytes: &[u8]) -> Value {
+        decode(type_name, bytes).unwrap()
+    }
+
+    #[test]
+    fn test_signed_integers() {
+        assert_eq!(decoded("TINYINT", &[0xff]), json!(-1));
+        assert_eq!(decoded("SMALLINT", &(-300i16).to_le_bytes()), json!(-300));
+        assert_eq!(
+            decoded("MEDIUMINT", &(-8_388_608i32).to_le_bytes()),
+            json!(-8388608)
+        );
+        assert_eq!(decoded("INT", &i32::MIN.to_le_bytes()), json!(i32::MIN));
+        // Small and huge values of a 64-bit column are both text
+        assert_eq!(
+            decoded("BIGINT", &i64::MIN.to_le_bytes()),
+            json!("-9223372036854775808")
+        );
+        assert_eq!(decoded("BIGINT", &(-42i64).to_le_bytes()), json!("-42"));
+        assert!(decode("INT", &[1, 0]).is_err());
+    }
+
+    #[test]
+    fn test_unsigned_integers() {
+        assert_eq!(decoded("TINYINT UNSIGNED", &[0xff]), json!(255));
+        assert_eq!(decoded("SMALLINT UNSIGNED", &[0xff, 0xff]), json!(65535));
+        assert_eq!(
+            decoded("INT UNSIGNED", &u32::MAX.to_le_bytes()),
+            json!(4294967295u32)
+        );
+        assert_eq!(
+            decoded("BIGINT UNSIGNED", &u64::MAX.to_le_bytes()),
+            json!("18446744073709551615")
+        );
+        assert_eq!(decoded("BIGINT UNSIGNED", &7u64.to_le_bytes()), json!("7"));
+        assert_eq!(decoded("YEAR", &2024u16.to_le_bytes()), json!(2024));
+    }
+
+    #[test]
+    fn test_booleans_and_bits() {
+        assert_eq!(decoded("BOOLEAN", &[1]), json!(true));
+        assert_eq!(decoded("BOOLEAN", &[0]), json!(false));
+        assert_eq!(decoded("BOOLEAN", &[5]), json!(5));
+        assert_eq!(decoded("BIT", &[1]), json!("1"));
+        assert_eq!(decoded("BIT", &[0x01, 0x02]), json!("258"));
+    }
+
+    #[test]
+    fn test_floats_and_decimals() {
+        assert_eq!(decoded("FLOAT", &0.1f32.to_le_bytes()), json!(0.1));
+        assert_eq!(decoded("DOUBLE", &(-2.5f64).to_le_bytes()), json!(-2.5));
+        assert_eq!(
+            decoded("DECIMAL", b"12345678901234567890123456789.0123456789"),
+            json!("12345678901234567890123456789.0123456789")
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(
+            decoded("DATE", &[4, 0xe8, 0x07, 2, 29]),
+            json!("2024-02-29")
+        );
+        assert_eq!(decoded("DATE", &[0]), json!("0000-00-00"));
+        assert_eq!(
+            decoded("DATETIME", &[7, 0xe8, 0x07, 2, 29, 13, 5, 9]),
+            json!("2024-02-29T13:05:09")
+        );
+        let mut bytes = vec![11, 0xe8, 0x07, 2, 29, 13, 5, 9];
+        bytes.extend_from_slice(&123_456u32.to_le_bytes());
+        assert_eq!(
+            decoded("TIMESTAMP", &bytes),
+            json!("2024-02-29T13:05:09.123456")
+        );
+        assert_eq!(
+            decoded("DATETIME", &[4, 0xe8, 0x07, 1, 1]),
+            json!("2024-01-01T00:00:00")
+        );
+        assert_eq!(decoded("DATETIME", &[0]), json!("0000-00-00T00:00:00"));
+        assert!(decode("DATE", &[3, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn test_times() {
+        assert_eq!(decoded("TIME", &[0]), json!("00:00:00"));
+        assert_eq!(
+            decoded("TIME", &[8, 0, 0, 0, 0, 0, 13, 30, 5]),
+            json!("13:30:05")
+        );
+        // -838:59:59, the smallest TIME
+        assert_eq!(
+            decoded("TIME", &[8, 1, 34, 0, 0, 0, 22, 59, 59]),
+            json!("-838:59:59")
+        );
+        let mut bytes = vec![12, 0, 1, 0, 0, 0, 1, 0, 0];
+        bytes.extend_from_slice(&500_000u32.to_le_bytes());
+        assert_eq!(decoded("TIME", &bytes), json!("25:00:00.500"));
+        // Malformed day counts must not overflow
+        let mut bytes = vec![8, 0];
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[1, 0, 0]);
+        assert_eq!(decoded("TIME", &bytes), json!("103079215081:00:00"));
+    }
+
+    #[test]
+    fn test_text_and_json() {
+        assert_eq!(decoded("VARCHAR", "naïve".as_bytes()), json!("naïve"));
+        assert_eq!(decoded("ENUM", b"small"), json!("small"));
+        assert_eq!(decoded("SET", b"a,b"), json!("a,b"));
+        assert_eq!(
+            decoded("JSON", br#"{"tags": ["x", 1], "n": null}"#),
+            json!({"tags": ["x", 1], "n": null})
+        );
+    }
+
+    #[test]
+    fn test_binary() {
+        assert_eq!(decoded("VARBINARY", &[0, 255, 16]), json!("AP8Q"));
+        assert_eq!(decoded("BLOB", &[]), json!(""));
+        // POINT(1 2) with SRID 0
+        let mut point = vec![0, 0, 0, 0, 1, 1, 0, 0, 0];
+        point.extend_from_slice(&1f64.to_le_bytes());
+        point.extend_from_slice(&2f64.to_le_bytes());
+        assert_eq!(
+            decoded("GEOMETRY", &point),
+            json!("AAAAAAEBAAAAAAAAAAAA8D8AAAAAAAAAQA==")
+        );
+        // Values of types without decoder are kept by `MySQLParser::values`
+        assert!(decode("VECTOR", b"abc").is_err());
+    }
+}
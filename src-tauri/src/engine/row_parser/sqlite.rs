@@ -0,0 +1,270 @@
+use std::ffi::CStr;
+use std::os::raw::c_int;
+use std::ptr;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use serde_json::Value;
+use sqlx::error::Error;
+use sqlx::sqlite::{SqliteConnection, SqliteRow, SqliteTypeInfo};
+use sqlx::{Column, Executor, Row, Statement, TypeInfo, ValueRef};
+
+use super::{big_integer, float, DecodedRow, JsonKind, ResultColumn};
+
+pub struct SQLiteParser;
+
+impl SQLiteParser {
+    /// Convert a row to its JSON values in column order. SQLite stores every value with its own
+    /// storage class whatever the column declares, so values are read by the class they have.
+    /// The declared type only tells integers used as booleans apart.
+    pub async fn values(row: &SqliteRow) -> Result<DecodedRow, Error> {
+        let mut values = Vec::new();
+
+        for column in row.columns() {
+            let index = column.ordinal();
+            let raw = row.try_get_raw(index)?;
+            // A NULL has the declared type of its column
+            let storage_class = match raw.is_null() {
+                true => "NULL".to_string(),
+                false => raw.type_info().name().to_string(),
+            };
+            let value = match storage_class.as_str() {
+                "NULL" => Value::Null,
+                "INTEGER" => {
+                    let val: i64 = row.try_get_unchecked(index)?;
+                    match (column.type_info().name(), val) {
+                        ("BOOLEAN", 0) => Value::Bool(false),
+                        ("BOOLEAN", 1) => Value::Bool(true),
+                        _ => big_integer(val),
+                    }
+                }
+                "REAL" => {
+                    let val: f64 = row.try_get_unchecked(index)?;
+                    float(val, false)
+                }
+                // Text is not checked to be UTF-8 when it is written, invalid bytes are
+                // replaced so the value is still text
+                "TEXT" => {
+                    let val: &[u8] = row.try_get_unchecked(index)?;
+                    Value::String(String::from_utf8_lossy(val).into_owned())
+                }
+                _ => {
+                    let val: &[u8] = row.try_get_unchecked(index)?;
+                    Value::String(STANDARD.encode(val))
+                }
+            };
+            values.push(value);
+        }
+
+        // Every storage class has a decoder, no value falls back
+        Ok(DecodedRow {
+            values,
+            fallbacks: Vec::new(),
+        })
+    }
+
+    /// Describe the result columns of `query` with the type they are declared with, as written
+    /// in the table, or an empty type for expressions. sqlx only names the types it maps and
+    /// reports the others as NULL. `describe` walks every branch of the program, which can take
+    /// very long, so nullability is left unknown.
+    pub async fn columns(
+        conn: &mut SqliteConnection,
+        query: &str,
+    ) -> Result<Vec<ResultColumn>, Error> {
+        // The prepared statement is cached and reused by the query
+        let statement = (&mut *conn).prepare(query).await?;
+        let mut columns = super::columns(statement.columns(), |_| None, Self::kind);
+        let mut handle = conn.lock_handle().await?;
+        let declared = declared_types(handle.as_raw_handle().as_ptr(), query)?;
+        for (column, declared) in columns.iter_mut().zip(declared) {
+            column.db_type = declared.unwrap_or_default();
+        }
+        Ok(columns)
+    }
+
+    /// How values of a column with the declared type are usually written. Values that do not
+    /// fit the affinity of their column keep their own storage class, so every type is decoded.
+    pub fn kind(type_info: &SqliteTypeInfo) -> Option<JsonKind> {
+        let kind = match type_info.name() {
+            "BOOLEAN" => JsonKind::Boolean,
+            "INTEGER" => JsonKind::BigInt,
+            "REAL" => JsonKind::Float,
+            "TEXT" => JsonKind::String,
+            "BLOB" => JsonKind::Binary,
+            "DATE" | "TIME" | "DATETIME" => JsonKind::Temporal,
+            _ => JsonKind::Mixed,
+        };
+        Some(kind)
+    }
+}
+
+// Declared type of every result column of the first statement of `query`, None for expressions
+fn declared_types(
+    db: *mut libsqlite3_sys::sqlite3,
+    query: &str,
+) -> Result<Vec<Option<String>>, Error> {
+    let len = c_int::try_from(query.len()).map_err(|e| Error::Protocol(e.to_string()))?;
+    let mut declared = Vec::new();
+    // Safety: the connection is locked by the caller and the statement is finalized before
+    // returning, the declared types are copied while it is alive
+    unsafe {
+        let mut statement = ptr::null_mut();
+        let code = libsqlite3_sys::sqlite3_prepare_v2(
+            db,
+            query.as_ptr().cast(),
+            len,
+            &mut statement,
+            ptr::null_mut(),
+        );
+        if code != libsqlite3_sys::SQLITE_OK {
+            let message = CStr::from_ptr(libsqlite3_sys::sqlite3_errmsg(db));
+            return Err(Error::Protocol(message.to_string_lossy().into_owned()));
+        }
+        for index in 0..libsqlite3_sys::sqlite3_column_count(statement) {
+            let decltype = libsqlite3_sys::sqlite3_column_decltype(statement, index);
+            declared.push(match decltype.is_null() {
+                true => None,
+                false => Some(CStr::from_ptr(decltype).to_string_lossy().into_owned()),
+            });
+        }
+        libsqlite3_sys::sqlite3_finalize(statement);
+    }
+    Ok(declared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::Executor;
+
+    #[tokio::test]
+    async fn test_values_by_storage_class() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        pool.execute(
+            "CREATE TABLE items (id INTEGER, name VARCHAR(50), price NUMERIC, qty BIGINT,
+                 data BLOB, active BOOLEAN, added DATETIME, anything);
+             INSERT INTO items VALUES
+                 (1, 'pen', 1.5, 10, x'00ff10', 1, '2024-02-29 12:00:00', 'text'),
+                 (2, NULL, 3, NULL, NULL, 0, 1709208000, x'01'),
+                 (NULL, 'cap', '9e999', 5, '', 2, NULL, 4.25);",
+        )
+        .await
+        .unwrap();
+
+        let rows = sqlx::query("SELECT * FROM items ORDER BY id IS NULL, id")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        let mut values = Vec::new();
+        for row in &rows {
+            values.push(SQLiteParser::values(row).await.unwrap().values);
+        }
+        assert_eq!(
+            values,
+            vec![
+                vec![
+                    json!("1"),
+                    json!("pen"),
+                    json!(1.5),
+                    json!("10"),
+                    json!("AP8Q"),
+                    json!(true),
+                    json!("2024-02-29 12:00:00"),
+                    json!("text")
+                ],
+                // NUMERIC affinity stores 3 as an integer
+                vec![
+                    json!("2"),
+                    json!(null),
+                    json!("3"),
+                    json!(null),
+                    json!(null),
+                    json!(false),
+                    json!("1709208000"),
+                    json!("AQ==")
+                ],
+                // Too large for a real, stored as infinity
+                vec![
+                    json!(null),
+                    json!("cap"),
+                    json!("Infinity"),
+                    json!("5"),
+                    json!(""),
+                    json!("2"),
+                    json!(null),
+                    json!(4.25)
+                ],
+            ]
+        );
+
+        let mut conn = pool.acquire().await.unwrap();
+        let columns = SQLiteParser::columns(&mut conn, "SELECT * FROM items")
+            .await
+            .unwrap();
+        let types: Vec<&str> = columns.iter().map(|c| c.db_type.as_str()).collect();
+        assert_eq!(
+            types,
+            vec![
+                "INTEGER",
+                "VARCHAR(50)",
+                "NUMERIC",
+                "BIGINT",
+                "BLOB",
+                "BOOLEAN",
+                "DATETIME",
+                ""
+            ]
+        );
+        let kinds: Vec<JsonKind> = columns.iter().map(|column| column.json).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                JsonKind::BigInt,
+                JsonKind::String,
+                JsonKind::Mixed,
+                JsonKind::BigInt,
+                JsonKind::Binary,
+                JsonKind::Boolean,
+                JsonKind::Temporal,
+                JsonKind::Mixed,
+            ]
+        );
+
+        // Computed columns have no declared type
+        let query = "SELECT COUNT(*) AS n, AVG(qty) AS mean, NULL AS missing FROM items";
+        let columns = SQLiteParser::columns(&mut conn, query).await.unwrap();
+        let types: Vec<&str> = columns.iter().map(|c| c.db_type.as_str()).collect();
+        assert_eq!(types, vec!["", "", ""]);
+        let row = sqlx::query(query).fetch_one(&mut conn).await.unwrap();
+        assert_eq!(
+            SQLiteParser::values(&row).await.unwrap().values,
+            vec![json!("3"), json!(7.5), json!(null)]
+        );
+
+        // Small and huge integers of a column are both text
+        let rows = sqlx::query("SELECT 1 AS n UNION ALL SELECT 9223372036854775807")
+            .fetch_all(&mut conn)
+            .await
+            .unwrap();
+        let mut values = Vec::new();
+        for row in &rows {
+            values.extend(SQLiteParser::values(row).await.unwrap().values);
+        }
+        assert_eq!(values, vec![json!("1"), json!("9223372036854775807")]);
+
+        let row = sqlx::query("SELECT CAST(x'61ff62' AS TEXT) AS broken")
+            .fetch_one(&mut conn)
+            .await
+            .unwrap();
+        assert_eq!(
+            SQLiteParser::values(&row).await.unwrap().values,
+            vec![json!("a\u{fffd}b")]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{cache, Schema, Table};
+use crate::engine::config::Config;
+use crate::engine::errors::AppError;
+
+/// Descriptions written by the user for tables and columns, for SQLite databases or tables the
+/// database does not document. Saved per connection as `<data_dir>/annotations/<key>.json`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Annotations {
+    // By table name as written in queries without quotes, e.g. `orders` or `billing.invoices`
+    #[serde(default)]
+    pub tables: BTreeMap<String, TableAnnotation>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableAnnotation {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    // Comment by column name, e.g. `"amt": "in cents"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub columns: BTreeMap<String, String>,
+}
+
+impl Annotations {
+    /// Set the annotated comments on the schema, they win over comments from the database
+    pub fn apply(&self, schema: &mut Schema) {
+        for table in &mut schema.tables {
+            let annotation = match self.tables.get(&key(table)) {
+                Some(annotation) => annotation,
+                None => continue,
+            };
+            if let Some(comment) = non_empty(annotation.comment.as_deref()) {
+                table.comment = Some(comment);
+            }
+            for column in &mut table.columns {
+                if let Some(comment) =
+                    non_empty(annotation.columns.get(&column.name).map(String::as_str))
+                {
+                    column.comment = Some(comment);
+                }
+            }
+        }
+    }
+}
+
+/// The annotations of the connection, empty when none were saved
+pub fn read(config: &Config) -> Result<Annotations, AppError> {
+    let path = match path(config) {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(Annotations::default()),
+    };
+    let content = std::fs::read_to_string(&path)?;
+    serde_json::from_str(&content).map_err(|e| {
+        AppError::ConfigError(format!("Invalid annotations {}: {}", path.display(), e))
+    })
+}
+
+/// Save the annotations of the connection
+pub fn write(config: &Config, annotations: &Annotations) -> Result<(), AppError> {
+    let path = path(config).ok_or_else(|| {
+        AppError::ConfigError("No app data directory to save annotations in.".to_string())
+    })?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let content = serde_json::to_string_pretty(annotations)
+        .map_err(|e| AppError::ExecutionError(format!("Cannot save annotations: {}", e)))?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+// Annotations stay with the database when the exposed schemas change
+fn path(config: &Config) -> Option<std::path::PathBuf> {
+    cache::connection_file(
+        config,
+        "annotations",
+        &[config.db_type.to_string(), config.connection_string.clone()],
+    )
+}
+
+fn key(table: &Table) -> String {
+    match &table.schema {
+        Some(schema) => format!("{}.{}", schema, table.name),
+        None => table.name.clone(),
+    }
+}
+
+fn non_empty(comment: Option<&str>) -> Option<String> {
+    comment
+        .map(str::trim)
+        .filter(|comment| !comment.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::schema::Column;
+
+    #[test]
+    fn test_apply_annotations() {
+        let column = |name: &str, comment: Option<&str>| Column {
+            name: name.to_string(),
+            data_type: "integer".to_string(),
+            nullable: true,
+            comment: comment.map(str::to_string),
+            ..Column::default()
+        };
+        let mut schema = Schema {
+            tables: vec![
+                Table {
+                    name: "payments".to_string(),
+                    comment: Some("Card payments".to_string()),
+                    columns: vec![column("amt", None), column("fee", Some("Bank fee"))],
+                    ..Table::default()
+                },
+                Table {
+                    schema: Some("billing".to_string()),
+                    name: "invoices".to_string(),
+                    columns: vec![column("amt", None)],
+                    ..Table::default()
+                },
+            ],
+        };
+        let annotations: Annotations = serde_json::from_str(
+            r#"{"tables": {
+                "payments": {"columns": {"amt": "in cents", "fee": " "}},
+                "billing.invoices": {"comment": "Sent every month"}
+            }}"#,
+        )
+        .unwrap();
+
+        annotations.apply(&mut schema);
+        let payments = &schema.tables[0];
+        assert_eq!(payments.comment.as_deref(), Some("Card payments"));
+        assert_eq!(payments.columns[0].comment.as_deref(), Some("in cents"));
+        assert_eq!(payments.columns[1].comment.as_deref(), Some("Bank fee"));
+        assert_eq!(
+            schema.tables[1].comment.as_deref(),
+            Some("Sent every month")
+        );
+        assert_eq!(schema.tables[1].columns[0].comment, None);
+    }
+}
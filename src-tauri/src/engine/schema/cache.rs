@@ -0,0 +1,122 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::Schema;
+use crate::engine::config::Config;
+use crate::engine::errors::AppError;
+
+// A schema saved in the app data directory with the fingerprint of the database it was read from.
+// Profiles are not saved, they hold rows of the database.
+#[derive(Serialize, Deserialize)]
+struct CachedSchema {
+    fingerprint: String,
+    schema: Schema,
+}
+
+/// Hash of the catalog rows describing the schema, changes whenever a table, column, key or
+/// index does. FNV-1a, so the value stays the same across builds of the app.
+pub fn fingerprint(items: &[String]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for item in items {
+        for byte in item.bytes().chain(std::iter::once(b'\n')) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// The cached schema of the connection, None when there is none or the database changed since
+pub fn read(config: &Config, fingerprint: &str) -> Option<Schema> {
+    let content = std::fs::read_to_string(path(config)?).ok()?;
+    let cached: CachedSchema = serde_json::from_str(&content).ok()?;
+    if cached.fingerprint == fingerprint {
+        Some(cached.schema)
+    } else {
+        None
+    }
+}
+
+/// Save the schema of the connection, nothing is saved without an app data directory
+pub fn write(config: &Config, fingerprint: &str, schema: &Schema) -> Result<(), AppError> {
+    let path = match path(config) {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let cached = CachedSchema {
+        fingerprint: fingerprint.to_string(),
+        schema: schema.clone(),
+    };
+    let content = serde_json::to_string(&cached)
+        .map_err(|e| AppError::ExecutionError(format!("Cannot save the schema: {}", e)))?;
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+// `<data_dir>/schema-cache/<key>.json`, keyed by everything selecting the tables
+fn path(config: &Config) -> Option<PathBuf> {
+    connection_file(
+        config,
+        "schema-cache",
+        &[
+            config.db_type.to_string(),
+            config.connection_string.clone(),
+            config.schemas.join(","),
+        ],
+    )
+}
+
+/// `<data_dir>/<folder>/<key>.json` where the key is a hash of `key`, so the connection string
+/// with its password is not written to disk. None without an app data directory.
+pub fn connection_file(config: &Config, folder: &str, key: &[String]) -> Option<PathBuf> {
+    if config.data_dir.is_empty() {
+        return None;
+    }
+    Some(
+        Path::new(&config.data_dir)
+            .join(folder)
+            .join(format!("{}.json", fingerprint(key))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::schema::Table;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("schema-cache-test-{}", std::process::id()));
+        let mut config: Config = serde_json::from_value(serde_json::json!({
+            "db_type": "SQLite",
+            "connection_string": "sqlite://shop.db",
+            "ai_cli_path": "",
+            "ai_model_path": "",
+            "sql_knowledge": "",
+        }))
+        .unwrap();
+        config.data_dir = dir.to_string_lossy().to_string();
+        let schema = Schema {
+            tables: vec![Table {
+                name: "orders".to_string(),
+                ..Table::default()
+            }],
+        };
+        let items = vec!["table orders CREATE TABLE orders (id)".to_string()];
+
+        write(&config, &fingerprint(&items), &schema).unwrap();
+        assert_eq!(read(&config, &fingerprint(&items)), Some(schema));
+        // A new column changes the fingerprint and the cached schema is not used
+        let changed = vec!["table orders CREATE TABLE orders (id, total)".to_string()];
+        assert_eq!(read(&config, &fingerprint(&changed)), None);
+        // Another connection has its own entry
+        config.connection_string = "sqlite://other.db".to_string();
+        assert_eq!(read(&config, &fingerprint(&items)), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
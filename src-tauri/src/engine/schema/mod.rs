@@ -0,0 +1,428 @@
+pub mod annotations;
+pub mod cache;
+pub mod mysql;
+pub mod postgres;
+pub mod profile;
+pub mod sqlite;
+
+use serde::{Deserialize, Serialize};
+
+use super::config::DbType;
+use super::errors::AppError;
+
+// Words that cannot be used as bare identifiers in any of the dialects
+const RESERVED: &[&str] = &[
+    "all",
+    "and",
+    "as",
+    "asc",
+    "between",
+    "by",
+    "case",
+    "cast",
+    "check",
+    "column",
+    "create",
+    "default",
+    "delete",
+    "desc",
+    "distinct",
+    "drop",
+    "else",
+    "end",
+    "except",
+    "from",
+    "group",
+    "having",
+    "in",
+    "index",
+    "insert",
+    "intersect",
+    "is",
+    "join",
+    "key",
+    "left",
+    "like",
+    "limit",
+    "not",
+    "null",
+    "offset",
+    "on",
+    "or",
+    "order",
+    "primary",
+    "references",
+    "select",
+    "table",
+    "then",
+    "to",
+    "union",
+    "update",
+    "user",
+    "using",
+    "values",
+    "when",
+    "where",
+    "with",
+];
+
+/// The tables of a connected database, read the same way for every database type
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    // Schema (PostgreSQL) or database (MySQL) of the table, None when it is the default one
+    // of the connection and the name can be used unqualified
+    pub schema: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub kind: TableKind,
+    // Documentation from the database or the local annotations
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    // Rows according to the database statistics when the schema was read, None for views and
+    // tables never analyzed. Not part of the fingerprint, so it can be out of date.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_estimate: Option<i64>,
+    pub columns: Vec<Column>,
+    pub primary_key: Vec<String>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub indexes: Vec<Index>,
+}
+
+/// What a table of the schema is, views are queried like tables
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum TableKind {
+    #[default]
+    Table,
+    View,
+    MaterializedView,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    // Type as declared in the database, e.g. `character varying(50)` or `int unsigned`
+    pub data_type: String,
+    pub nullable: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    // Allowed values of an enum type, in their declared order
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enum_values: Vec<String>,
+    // Computed from other columns, it can be selected but not written
+    #[serde(default)]
+    pub generated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    pub referenced_schema: Option<String>,
+    pub referenced_table: String,
+    pub referenced_columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+impl Schema {
+    /// The table with this schema and name
+    pub fn table(&self, schema: Option<&str>, name: &str) -> Option<&Table> {
+        self.tables
+            .iter()
+            .find(|table| table.schema.as_deref() == schema && table.name == name)
+    }
+
+    /// The table with this schema and name, added at the end when it is not there yet
+    pub fn table_mut(&mut self, schema: Option<String>, name: &str) -> &mut Table {
+        let index = match self
+            .tables
+            .iter()
+            .position(|table| table.schema == schema && table.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.tables.push(Table {
+                    schema,
+                    name: name.to_string(),
+                    ..Table::default()
+                });
+                self.tables.len() - 1
+            }
+        };
+        &mut self.tables[index]
+    }
+
+    /// Render the schema as compact `CREATE TABLE` and `CREATE VIEW` statements, one line per
+    /// table, for the prompt. Non-unique indexes are left out, they do not change what a query
+    /// can express. Comments follow their column as `/* .. */` and their table as `-- ..`.
+    pub fn to_ddl(&self, db_type: &DbType) -> String {
+        let mut ddl = String::new();
+        for table in &self.tables {
+            let mut parts: Vec<String> = Vec::new();
+            for column in &table.columns {
+                let mut part = format!(
+                    "{} {}",
+                    quote_identifier(&column.name, db_type),
+                    column.data_type
+                );
+                if column.generated {
+                    part.push_str(" GENERATED ALWAYS");
+                }
+                if table.primary_key == [column.name.as_str()] {
+                    part.push_str(" PRIMARY KEY");
+                } else if !column.nullable {
+                    part.push_str(" NOT NULL");
+                }
+                // MySQL lists the values in the type already, `enum('a','b')`
+                if !column.enum_values.is_empty()
+                    && !column.data_type.to_lowercase().starts_with("enum(")
+                {
+                    let values: Vec<String> = column
+                        .enum_values
+                        .iter()
+                        .map(|value| format!("'{}'", value.replace('\'', "''")))
+                        .collect();
+                    part.push_str(&format!(
+                        " CHECK ({} IN ({}))",
+                        quote_identifier(&column.name, db_type),
+                        values.join(", ")
+                    ));
+                }
+                if let Some(fk) = table
+                    .foreign_keys
+                    .iter()
+                    .find(|fk| fk.columns == [column.name.as_str()])
+                {
+                    part.push_str(&format!(
+                        " REFERENCES {}({})",
+                        fk.qualified_table(db_type),
+                        column_list(&fk.referenced_columns, db_type)
+                    ));
+                }
+                if let Some(comment) = &column.comment {
+                    part.push_str(&format!(
+                        " /* {} */",
+                        single_line(comment).replace("*/", "* /")
+                    ));
+                }
+                parts.push(part);
+            }
+            if table.primary_key.len() > 1 {
+                parts.push(format!(
+                    "PRIMARY KEY ({})",
+                    column_list(&table.primary_key, db_type)
+                ));
+            }
+            for fk in table.foreign_keys.iter().filter(|fk| fk.columns.len() > 1) {
+                parts.push(format!(
+                    "FOREIGN KEY ({}) REFERENCES {}({})",
+                    column_list(&fk.columns, db_type),
+                    fk.qualified_table(db_type),
+                    column_list(&fk.referenced_columns, db_type)
+                ));
+            }
+            for index in table
+                .indexes
+                .iter()
+                .filter(|index| index.unique && index.columns != table.primary_key)
+            {
+                parts.push(format!("UNIQUE ({})", column_list(&index.columns, db_type)));
+            }
+            ddl.push_str(&format!(
+                "CREATE {} {} ({});",
+                table.kind.keyword(),
+                table.qualified_name(db_type),
+                parts.join(", ")
+            ));
+            if let Some(comment) = &table.comment {
+                ddl.push_str(&format!(" -- {}", single_line(comment)));
+            }
+            ddl.push('\n');
+        }
+        ddl
+    }
+}
+
+impl Table {
+    /// The name to use in queries, prefixed with the schema outside the default one
+    pub fn qualified_name(&self, db_type: &DbType) -> String {
+        qualify(self.schema.as_deref(), &self.name, db_type)
+    }
+}
+
+impl TableKind {
+    /// The word creating this kind of table in SQL
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            TableKind::Table => "TABLE",
+            TableKind::View => "VIEW",
+            TableKind::MaterializedView => "MATERIALIZED VIEW",
+        }
+    }
+}
+
+impl ForeignKey {
+    pub fn qualified_table(&self, db_type: &DbType) -> String {
+        qualify(
+            self.referenced_schema.as_deref(),
+            &self.referenced_table,
+            db_type,
+        )
+    }
+}
+
+fn qualify(schema: Option<&str>, name: &str, db_type: &DbType) -> String {
+    match schema {
+        Some(schema) => format!(
+            "{}.{}",
+            quote_identifier(schema, db_type),
+            quote_identifier(name, db_type)
+        ),
+        None => quote_identifier(name, db_type),
+    }
+}
+
+/// The name as it has to be written in SQL, quoted when it is not a plain lowercase word
+pub fn quote_identifier(name: &str, db_type: &DbType) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_lowercase() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        && !RESERVED.contains(&name);
+    if plain {
+        return name.to_string();
+    }
+    match db_type {
+        DbType::MySQL => format!("`{}`", name.replace('`', "``")),
+        DbType::PostgreSQL | DbType::SQLite => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+// Comments may span lines, the prompt keeps a table on one
+fn single_line(comment: &str) -> String {
+    comment.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn column_list(columns: &[String], db_type: &DbType) -> String {
+    columns
+        .iter()
+        .map(|column| quote_identifier(column, db_type))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The first column of every row, for the catalog queries `fingerprint` hashes
+fn items<R: sqlx::Row>(rows: Vec<R>) -> Result<Vec<String>, AppError>
+where
+    for<'r> String: sqlx::Decode<'r, R::Database> + sqlx::Type<R::Database>,
+    usize: sqlx::ColumnIndex<R>,
+{
+    rows.iter()
+        .map(|row| row.try_get(0))
+        .collect::<Result<_, _>>()
+        .map_err(introspection_error)
+}
+
+fn introspection_error(e: sqlx::Error) -> AppError {
+    AppError::QueryError(format!("Failed to read the database schema: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(name: &str, data_type: &str, nullable: bool) -> Column {
+        Column {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable,
+            ..Column::default()
+        }
+    }
+
+    #[test]
+    fn test_to_ddl() {
+        let schema = Schema {
+            tables: vec![
+                Table {
+                    name: "students".to_string(),
+                    comment: Some("Enrolled students".to_string()),
+                    columns: vec![
+                        column("id", "integer", false),
+                        column("email", "text", false),
+                        column("class_id", "integer", true),
+                        Column {
+                            comment: Some("Shown in\n class */".to_string()),
+                            ..column("Nick Name", "text", true)
+                        },
+                    ],
+                    primary_key: vec!["id".to_string()],
+                    foreign_keys: vec![ForeignKey {
+                        columns: vec!["class_id".to_string()],
+                        referenced_schema: None,
+                        referenced_table: "classes".to_string(),
+                        referenced_columns: vec!["id".to_string()],
+                    }],
+                    indexes: vec![
+                        Index {
+                            name: "students_email_key".to_string(),
+                            columns: vec!["email".to_string()],
+                            unique: true,
+                        },
+                        Index {
+                            name: "students_class".to_string(),
+                            columns: vec!["class_id".to_string()],
+                            unique: false,
+                        },
+                    ],
+                    ..Table::default()
+                },
+                Table {
+                    schema: Some("Billing".to_string()),
+                    name: "grades".to_string(),
+                    columns: vec![
+                        column("student_id", "integer", false),
+                        column("course", "text", false),
+                    ],
+                    primary_key: vec!["student_id".to_string(), "course".to_string()],
+                    ..Table::default()
+                },
+                Table {
+                    name: "report".to_string(),
+                    kind: TableKind::View,
+                    columns: vec![
+                        Column {
+                            enum_values: vec!["open".to_string(), "won't fix".to_string()],
+                            ..column("state", "ticket_state", true)
+                        },
+                        Column {
+                            generated: true,
+                            ..column("total", "numeric", true)
+                        },
+                    ],
+                    ..Table::default()
+                },
+            ],
+        };
+        assert_eq!(
+            schema.to_ddl(&DbType::PostgreSQL),
+            "CREATE TABLE students (id integer PRIMARY KEY, email text NOT NULL, \
+             class_id integer REFERENCES classes(id), \"Nick Name\" text /* Shown in class * / */, \
+             UNIQUE (email)); -- Enrolled students\n\
+             CREATE TABLE \"Billing\".grades (student_id integer NOT NULL, course text NOT NULL, \
+             PRIMARY KEY (student_id, course));\n\
+             CREATE VIEW report (state ticket_state CHECK (state IN ('open', 'won''t fix')), \
+             total numeric GENERATED ALWAYS);\n"
+        );
+        assert!(schema.to_ddl(&DbType::MySQL).contains("`Nick Name` text"));
+    }
+}
@@ -0,0 +1,284 @@
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::Query;
+use sqlx::{MySql, Pool, Row};
+
+use super::{cache, introspection_error, items, Column, ForeignKey, Index, Schema, TableKind};
+use crate::engine::errors::AppError;
+
+// `{filter}` is replaced with the condition selecting the databases, see `load`.
+// information_schema columns are cast to CHAR, MySQL 8 reports some of them as binary strings
+const COLUMNS: &str = r#"
+SELECT
+    IF(table_schema = DATABASE(), NULL, CAST(table_schema AS CHAR)) AS table_schema,
+    CAST(table_name AS CHAR) AS table_name,
+    CAST(column_name AS CHAR) AS column_name,
+    CAST(column_type AS CHAR) AS data_type,
+    CAST(is_nullable AS CHAR) AS is_nullable,
+    CAST(extra AS CHAR) AS extra,
+    CAST(NULLIF(column_comment, '') AS CHAR) AS column_comment,
+    (
+        SELECT CAST(t.table_type AS CHAR)
+        FROM information_schema.tables t
+        WHERE t.table_schema = c.table_schema AND t.table_name = c.table_name
+    ) AS table_type,
+    (
+        SELECT CAST(t.table_rows AS SIGNED)
+        FROM information_schema.tables t
+        WHERE t.table_schema = c.table_schema AND t.table_name = c.table_name
+    ) AS row_estimate,
+    (
+        SELECT CAST(NULLIF(t.table_comment, '') AS CHAR)
+        FROM information_schema.tables t
+        WHERE t.table_schema = c.table_schema
+            AND t.table_name = c.table_name
+            AND t.table_type <> 'VIEW'
+    ) AS table_comment
+FROM information_schema.columns c
+WHERE {filter}
+ORDER BY table_schema, table_name, ordinal_position
+"#;
+
+// One row per indexed column, functional index parts have no column name
+const INDEXES: &str = r#"
+SELECT
+    IF(table_schema = DATABASE(), NULL, CAST(table_schema AS CHAR)) AS table_schema,
+    CAST(table_name AS CHAR) AS table_name,
+    CAST(index_name AS CHAR) AS index_name,
+    CAST(non_unique AS SIGNED) AS non_unique,
+    CAST(column_name AS CHAR) AS column_name
+FROM information_schema.statistics
+WHERE {filter}
+ORDER BY table_schema, table_name, index_name, seq_in_index
+"#;
+
+// One row per column of a foreign key, only foreign keys fill `referenced_table_name`
+const FOREIGN_KEYS: &str = r#"
+SELECT
+    IF(table_schema = DATABASE(), NULL, CAST(table_schema AS CHAR)) AS table_schema,
+    CAST(table_name AS CHAR) AS table_name,
+    CAST(constraint_name AS CHAR) AS constraint_name,
+    CAST(column_name AS CHAR) AS column_name,
+    IF(referenced_table_schema = DATABASE(), NULL, CAST(referenced_table_schema AS CHAR))
+        AS referenced_schema,
+    CAST(referenced_table_name AS CHAR) AS referenced_table,
+    CAST(referenced_column_name AS CHAR) AS referenced_column
+FROM information_schema.key_column_usage
+WHERE {filter} AND referenced_table_name IS NOT NULL
+ORDER BY table_schema, table_name, constraint_name, ordinal_position
+"#;
+
+// Everything `load` reads, one line per column, table, index column and foreign key column.
+// The filter is used four times.
+const FINGERPRINT: &str = r#"
+SELECT CAST(CONCAT('current ', COALESCE(DATABASE(), '')) AS CHAR) AS item
+UNION ALL
+SELECT CAST(CONCAT_WS(' ', table_schema, table_name, column_name, column_type, is_nullable,
+    extra, column_comment) AS CHAR)
+FROM information_schema.columns
+WHERE {filter}
+UNION ALL
+SELECT CAST(CONCAT_WS(' ', table_schema, table_name, table_type, table_comment) AS CHAR)
+FROM information_schema.tables
+WHERE {filter}
+UNION ALL
+SELECT CAST(CONCAT_WS(' ', table_schema, table_name, index_name, seq_in_index, column_name,
+    non_unique) AS CHAR)
+FROM information_schema.statistics
+WHERE {filter}
+UNION ALL
+SELECT CAST(CONCAT_WS(' ', table_schema, table_name, constraint_name, ordinal_position,
+    column_name, referenced_table_schema, referenced_table_name, referenced_column_name) AS CHAR)
+FROM information_schema.key_column_usage
+WHERE {filter} AND referenced_table_name IS NOT NULL
+ORDER BY 1
+"#;
+
+/// Fingerprint of the tables `load` reads, see `cache::fingerprint`
+pub async fn fingerprint(pool: &Pool<MySql>, databases: &[String]) -> Result<String, AppError> {
+    let sql = FINGERPRINT.replace("{filter}", &filter(databases));
+    let binds: Vec<String> = (0..4).flat_map(|_| databases.iter().cloned()).collect();
+    let rows = query(&sql, &binds)
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?;
+    Ok(cache::fingerprint(&items(rows)?))
+}
+
+/// Read the tables of the given databases, or of the database the connection string selects
+/// when none are given. Tables outside the selected database keep their database name.
+pub async fn load(pool: &Pool<MySql>, databases: &[String]) -> Result<Schema, AppError> {
+    let filter = filter(databases);
+    let columns = COLUMNS.replace("{filter}", &filter);
+    let indexes = INDEXES.replace("{filter}", &filter);
+    let foreign_keys = FOREIGN_KEYS.replace("{filter}", &filter);
+    let mut schema = Schema::default();
+
+    for row in query(&columns, databases)
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?
+    {
+        let table_schema: Option<String> =
+            row.try_get("table_schema").map_err(introspection_error)?;
+        let table: String = row.try_get("table_name").map_err(introspection_error)?;
+        let is_nullable: String = row.try_get("is_nullable").map_err(introspection_error)?;
+        let data_type: String = row.try_get("data_type").map_err(introspection_error)?;
+        // `VIRTUAL GENERATED` or `STORED GENERATED` for generated columns
+        let extra: String = row.try_get("extra").map_err(introspection_error)?;
+        let table_type: Option<String> = row.try_get("table_type").map_err(introspection_error)?;
+        let table = schema.table_mut(table_schema, &table);
+        table.kind = match table_type.as_deref() {
+            Some("VIEW") | Some("SYSTEM VIEW") => TableKind::View,
+            _ => TableKind::Table,
+        };
+        table.comment = row.try_get("table_comment").map_err(introspection_error)?;
+        // `table_rows` is NULL for views and approximate for InnoDB tables
+        table.row_estimate = row.try_get("row_estimate").map_err(introspection_error)?;
+        table.columns.push(Column {
+            name: row.try_get("column_name").map_err(introspection_error)?,
+            enum_values: enum_values(&data_type),
+            data_type,
+            nullable: is_nullable == "YES",
+            comment: row.try_get("column_comment").map_err(introspection_error)?,
+            generated: extra.to_uppercase().contains("GENERATED"),
+        });
+    }
+
+    for row in query(&indexes, databases)
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?
+    {
+        let table_schema: Option<String> =
+            row.try_get("table_schema").map_err(introspection_error)?;
+        let table: String = row.try_get("table_name").map_err(introspection_error)?;
+        let name: String = row.try_get("index_name").map_err(introspection_error)?;
+        let non_unique: i64 = row.try_get("non_unique").map_err(introspection_error)?;
+        let column: Option<String> = row.try_get("column_name").map_err(introspection_error)?;
+        let column = match column {
+            Some(column) => column,
+            None => continue,
+        };
+
+        let table = schema.table_mut(table_schema, &table);
+        if name == "PRIMARY" {
+            table.primary_key.push(column.clone());
+        }
+        match table.indexes.iter_mut().find(|index| index.name == name) {
+            Some(index) => index.columns.push(column),
+            None => table.indexes.push(Index {
+                name,
+                columns: vec![column],
+                unique: non_unique == 0,
+            }),
+        }
+    }
+
+    // Rows of the same constraint follow each other, a new name starts a new foreign key
+    let mut constraint = String::new();
+    for row in query(&foreign_keys, databases)
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?
+    {
+        let table_schema: Option<String> =
+            row.try_get("table_schema").map_err(introspection_error)?;
+        let table: String = row.try_get("table_name").map_err(introspection_error)?;
+        let name: String = row
+            .try_get("constraint_name")
+            .map_err(introspection_error)?;
+        let column: String = row.try_get("column_name").map_err(introspection_error)?;
+        let referenced_schema: Option<String> = row
+            .try_get("referenced_schema")
+            .map_err(introspection_error)?;
+        let referenced_table: String = row
+            .try_get("referenced_table")
+            .map_err(introspection_error)?;
+        let referenced_column: String = row
+            .try_get("referenced_column")
+            .map_err(introspection_error)?;
+
+        let key = format!("{:?}.{}.{}", table_schema, table, name);
+        let foreign_keys = &mut schema.table_mut(table_schema, &table).foreign_keys;
+        match foreign_keys.last_mut() {
+            Some(fk) if key == constraint => {
+                fk.columns.push(column);
+                fk.referenced_columns.push(referenced_column);
+            }
+            _ => foreign_keys.push(ForeignKey {
+                columns: vec![column],
+                referenced_schema,
+                referenced_table,
+                referenced_columns: vec![referenced_column],
+            }),
+        }
+        constraint = key;
+    }
+
+    Ok(schema)
+}
+
+/// The values of an `enum('a','b')` column type, quotes inside values are doubled
+fn enum_values(column_type: &str) -> Vec<String> {
+    let list = match column_type
+        .strip_prefix("enum(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        Some(list) => list,
+        None => return Vec::new(),
+    };
+    let mut values = Vec::new();
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = list.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' if quoted && chars.peek() == Some(&'\'') => {
+                value.push('\'');
+                chars.next();
+            }
+            '\'' if quoted => {
+                values.push(std::mem::take(&mut value));
+                quoted = false;
+            }
+            '\'' => quoted = true,
+            _ if quoted => value.push(c),
+            _ => {}
+        }
+    }
+    values
+}
+
+fn query<'q>(sql: &'q str, databases: &'q [String]) -> Query<'q, MySql, MySqlArguments> {
+    databases
+        .iter()
+        .fold(sqlx::query(sql), |query, database| query.bind(database))
+}
+
+// Condition selecting the tables of the databases, with a placeholder per database
+fn filter(databases: &[String]) -> String {
+    if databases.is_empty() {
+        "table_schema = DATABASE()".to_string()
+    } else {
+        format!(
+            "table_schema IN ({})",
+            vec!["?"; databases.len()].join(", ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enum_values() {
+        assert_eq!(
+            enum_values("enum('small','x, large','it''s')"),
+            vec!["small", "x, large", "it's"]
+        );
+        assert_eq!(enum_values("enum('')"), vec![""]);
+        assert!(enum_values("varchar(10)").is_empty());
+        assert!(enum_values("set('a','b')").is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+use std::future::Future;
+
+use serde::{Deserialize, Serialize};
+
+use super::{quote_identifier, Column, Table};
+use crate::engine::config::DbType;
+use crate::engine::errors::AppError;
+
+// Rows shown to the model for every table
+const SAMPLE_ROWS: usize = 3;
+// A text column with at most this many distinct values has them all listed
+const DISTINCT_VALUES: usize = 10;
+// Values are counted over the first rows only so profiling a large table stays cheap
+const PROFILED_ROWS: usize = 10000;
+// Longer values are cut in the prompt
+const MAX_VALUE_LEN: usize = 40;
+
+/// Example values of a table, giving the model the spelling of literals to compare with
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    // Columns of the sample rows, binary columns are left out
+    pub columns: Vec<String>,
+    pub sample_rows: Vec<Vec<Option<String>>>,
+    // Every value of low-cardinality text columns, most frequent first
+    pub values: Vec<(String, Vec<String>)>,
+    // Smallest and largest value of date and time columns
+    pub ranges: Vec<(String, String, String)>,
+    // Whether the table fits in the profiled rows, values and ranges are only a sample otherwise
+    #[serde(default)]
+    pub complete: bool,
+}
+
+/// Read a profile of the table, `fetch` runs a query and returns its columns as text
+pub async fn profile<F, Fut>(table: &Table, db_type: &DbType, fetch: F) -> Result<Profile, AppError>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<Vec<Vec<Option<String>>>, AppError>>,
+{
+    let name = table.qualified_name(db_type);
+    let mut profile = Profile::default();
+
+    let shown: Vec<&Column> = table.columns.iter().filter(|c| !is_binary(c)).collect();
+    if shown.is_empty() {
+        return Ok(profile);
+    }
+    let casts: Vec<String> = shown
+        .iter()
+        .map(|c| as_text(&quote_identifier(&c.name, db_type), db_type))
+        .collect();
+    profile.columns = shown.iter().map(|c| c.name.clone()).collect();
+    profile.sample_rows = fetch(format!(
+        "SELECT {} FROM {} LIMIT {}",
+        casts.join(", "),
+        name,
+        SAMPLE_ROWS
+    ))
+    .await?;
+    let counted = fetch(format!(
+        "SELECT {} FROM (SELECT 1 AS v FROM {} LIMIT {}) AS s",
+        as_text("COUNT(*)", db_type),
+        name,
+        PROFILED_ROWS + 1
+    ))
+    .await?;
+    profile.complete = match counted.first().and_then(|row| row.first()) {
+        Some(Some(count)) => count.parse::<usize>().map_or(false, |c| c <= PROFILED_ROWS),
+        _ => false,
+    };
+
+    for column in shown {
+        let first_rows = format!(
+            "(SELECT {} AS v FROM {} LIMIT {}) AS s",
+            quote_identifier(&column.name, db_type),
+            name,
+            PROFILED_ROWS
+        );
+        // Enum values are listed with the type already
+        if is_text(column) && column.enum_values.is_empty() {
+            let values = fetch(format!(
+                "SELECT {} FROM {} WHERE v IS NOT NULL GROUP BY v ORDER BY COUNT(*) DESC, v LIMIT {}",
+                as_text("v", db_type),
+                first_rows,
+                DISTINCT_VALUES + 1
+            ))
+            .await?;
+            if values.len() <= DISTINCT_VALUES {
+                let values: Vec<String> = values.into_iter().flatten().flatten().collect();
+                if !values.is_empty() {
+                    profile.values.push((column.name.clone(), values));
+                }
+            }
+        } else if is_temporal(column) {
+            let range = fetch(format!(
+                "SELECT {}, {} FROM {}",
+                as_text("MIN(v)", db_type),
+                as_text("MAX(v)", db_type),
+                first_rows
+            ))
+            .await?;
+            if let Some([Some(min), Some(max)]) = range.first().map(|row| row.as_slice()) {
+                profile
+                    .ranges
+                    .push((column.name.clone(), min.clone(), max.clone()));
+            }
+        }
+    }
+    Ok(profile)
+}
+
+/// Describe the profile in a few lines for the prompt
+pub fn describe(table: &Table, profile: &Profile, db_type: &DbType) -> String {
+    let name = table.qualified_name(db_type);
+    let mut text = String::new();
+    if !profile.sample_rows.is_empty() {
+        let rows: Vec<String> = profile
+            .sample_rows
+            .iter()
+            .map(|row| {
+                let values: Vec<String> = row
+                    .iter()
+                    .zip(&profile.columns)
+                    .map(|(value, column)| {
+                        let numeric = table
+                            .columns
+                            .iter()
+                            .any(|c| &c.name == column && is_numeric(c));
+                        match value {
+                            Some(value) if numeric => value.clone(),
+                            _ => literal(value.as_deref()),
+                        }
+                    })
+                    .collect();
+                format!("({})", values.join(", "))
+            })
+            .collect();
+        text.push_str(&format!(
+            "Rows of {} ({}): {}\n",
+            name,
+            profile.columns.join(", "),
+            rows.join(", ")
+        ));
+    }
+    // Past the profiled rows other values can exist, so they are only given as examples
+    let (one_of, range) = match profile.complete {
+        true => ("is one of", "goes from"),
+        false => ("has values such as", "has values seen from"),
+    };
+    for (column, values) in &profile.values {
+        let values: Vec<String> = values.iter().map(|v| literal(Some(v))).collect();
+        text.push_str(&format!(
+            "{}.{} {} {}\n",
+            name,
+            quote_identifier(column, db_type),
+            one_of,
+            values.join(", ")
+        ));
+    }
+    for (column, min, max) in &profile.ranges {
+        text.push_str(&format!(
+            "{}.{} {} {} to {}\n",
+            name,
+            quote_identifier(column, db_type),
+            range,
+            literal(Some(min)),
+            literal(Some(max))
+        ));
+    }
+    text
+}
+
+fn as_text(expression: &str, db_type: &DbType) -> String {
+    match db_type {
+        DbType::MySQL => format!("CAST({} AS CHAR)", expression),
+        DbType::PostgreSQL | DbType::SQLite => format!("CAST({} AS TEXT)", expression),
+    }
+}
+
+// A value as an SQL literal, cut when it is long
+fn literal(value: Option<&str>) -> String {
+    match value {
+        None => "NULL".to_string(),
+        Some(value) => {
+            let mut value: String = value.replace('\'', "''");
+            if value.chars().count() > MAX_VALUE_LEN {
+                value = value.chars().take(MAX_VALUE_LEN).collect();
+                value.push_str("...");
+            }
+            format!("'{}'", value)
+        }
+    }
+}
+
+fn data_type(column: &Column) -> String {
+    column.data_type.to_lowercase()
+}
+
+fn is_binary(column: &Column) -> bool {
+    let data_type = data_type(column);
+    ["blob", "bytea", "binary", "geometry"]
+        .iter()
+        .any(|t| data_type.contains(t))
+}
+
+fn is_numeric(column: &Column) -> bool {
+    let data_type = data_type(column);
+    [
+        "int", "real", "float", "double", "numeric", "decimal", "serial",
+    ]
+    .iter()
+    .any(|t| data_type.contains(t))
+        && !data_type.contains("interval")
+}
+
+fn is_text(column: &Column) -> bool {
+    let data_type = data_type(column);
+    ["char", "text", "enum", "clob"]
+        .iter()
+        .any(|t| data_type.contains(t))
+}
+
+fn is_temporal(column: &Column) -> bool {
+    let data_type = data_type(column);
+    ["date", "time"].iter().any(|t| data_type.contains(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::sqlite;
+    use super::*;
+    use crate::engine::sql_guard::ensure_read_only;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::{Executor, Row};
+
+    #[tokio::test]
+    async fn test_profile_sqlite_table() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        pool.execute(
+            "CREATE TABLE accounts (id INTEGER PRIMARY KEY, status TEXT, note TEXT,
+                 opened DATE, avatar BLOB);
+             INSERT INTO accounts (status, note, opened) VALUES
+                 ('active', 'a', '2021-03-01'), ('active', 'b', '2020-01-15'),
+                 ('closed', 'c', '2023-11-30'), (NULL, 'd', NULL), ('active', 'e', '2022-06-01'),
+                 ('active', 'f', NULL), ('active', 'g', NULL), ('active', 'h', NULL),
+                 ('active', 'i', NULL), ('active', 'j', NULL), ('active', 'k', NULL);",
+        )
+        .await
+        .unwrap();
+        let schema = sqlite::load(&pool).await.unwrap();
+        let fetch = |sql: String| {
+            let pool = pool.clone();
+            // Profiles are read through `Engine::query`, which refuses anything the guard does
+            assert!(ensure_read_only(&sql, &DbType::SQLite).is_ok(), "{}", sql);
+            async move {
+                let rows = sqlx::query(&sql).fetch_all(&pool).await.unwrap();
+                Ok(rows
+                    .iter()
+                    .map(|row| (0..row.len()).map(|i| row.get(i)).collect())
+                    .collect())
+            }
+        };
+
+        let profile = profile(&schema.tables[0], &DbType::SQLite, fetch)
+            .await
+            .unwrap();
+        assert_eq!(profile.columns, vec!["id", "status", "note", "opened"]);
+        assert_eq!(profile.sample_rows.len(), 3);
+        assert!(profile.complete);
+        // `note` has 11 distinct values, too many to list
+        assert_eq!(
+            profile.values,
+            vec![(
+                "status".to_string(),
+                vec!["active".to_string(), "closed".to_string()]
+            )]
+        );
+        assert_eq!(
+            profile.ranges,
+            vec![(
+                "opened".to_string(),
+                "2020-01-15".to_string(),
+                "2023-11-30".to_string()
+            )]
+        );
+        let text = describe(&schema.tables[0], &profile, &DbType::SQLite);
+        assert!(text.starts_with(
+            "Rows of accounts (id, status, note, opened): (1, 'active', 'a', '2021-03-01'), "
+        ));
+        assert!(text.contains("accounts.status is one of 'active', 'closed'\n"));
+        assert!(text.contains("accounts.opened goes from '2020-01-15' to '2023-11-30'\n"));
+
+        // Rows past the profiled ones add a status and a later date, so neither list is complete
+        pool.execute(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10000)
+             INSERT INTO accounts (status, note, opened) SELECT 'active', 'x', '2021-01-01' FROM n;
+             INSERT INTO accounts (status, note, opened) VALUES ('frozen', 'y', '2030-01-01');",
+        )
+        .await
+        .unwrap();
+        let profile = super::profile(&schema.tables[0], &DbType::SQLite, fetch)
+            .await
+            .unwrap();
+        assert!(!profile.complete);
+        let text = describe(&schema.tables[0], &profile, &DbType::SQLite);
+        assert!(text.contains("accounts.status has values such as 'active', 'closed'\n"));
+        assert!(
+            text.contains("accounts.opened has values seen from '2020-01-15' to '2023-11-30'\n")
+        );
+
+        for db_type in [DbType::PostgreSQL, DbType::MySQL] {
+            let fetch = |sql: String| {
+                assert!(ensure_read_only(&sql, &db_type).is_ok(), "{}", sql);
+                async { Ok(Vec::new()) }
+            };
+            super::profile(&schema.tables[0], &db_type, fetch)
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use sqlx::{Pool, Row, Sqlite};
+
+use super::{
+    cache, introspection_error, items, Column, ForeignKey, Index, Schema, Table, TableKind,
+};
+use crate::engine::errors::AppError;
+
+/// Fingerprint of the main database from the statements creating its tables and indexes,
+/// see `cache::fingerprint`
+pub async fn fingerprint(pool: &Pool<Sqlite>) -> Result<String, AppError> {
+    let rows = sqlx::query(
+        "SELECT type || ' ' || name || ' ' || COALESCE(sql, '') FROM sqlite_master ORDER BY type, name",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(introspection_error)?;
+    Ok(cache::fingerprint(&items(rows)?))
+}
+
+/// Read the tables and views of the main database
+pub async fn load(pool: &Pool<Sqlite>) -> Result<Schema, AppError> {
+    let mut schema = Schema::default();
+
+    let tables = sqlx::query(
+        "SELECT name, type FROM sqlite_master WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(introspection_error)?;
+
+    for row in tables {
+        let name: String = row.try_get("name").map_err(introspection_error)?;
+        let kind: String = row.try_get("type").map_err(introspection_error)?;
+        let mut table = Table {
+            name: name.clone(),
+            kind: match kind.as_str() {
+                "view" => TableKind::View,
+                _ => TableKind::Table,
+            },
+            ..Table::default()
+        };
+
+        // `pk` is the position of the column in the primary key, 0 when it is not part of it.
+        // `hidden` is 2 or 3 for generated columns, `table_info` leaves those out, and 1 for
+        // the hidden columns of virtual tables.
+        let mut primary_key: Vec<(i64, String)> = Vec::new();
+        for column in sqlx::query(
+            r#"SELECT name, type, "notnull", pk, hidden FROM pragma_table_xinfo(?) WHERE hidden <> 1"#,
+        )
+        .bind(&name)
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?
+        {
+            let column_name: String = column.try_get("name").map_err(introspection_error)?;
+            let not_null: i64 = column.try_get("notnull").map_err(introspection_error)?;
+            let pk: i64 = column.try_get("pk").map_err(introspection_error)?;
+            let hidden: i64 = column.try_get("hidden").map_err(introspection_error)?;
+            if pk > 0 {
+                primary_key.push((pk, column_name.clone()));
+            }
+            table.columns.push(Column {
+                name: column_name,
+                data_type: column.try_get("type").map_err(introspection_error)?,
+                nullable: not_null == 0 && pk == 0,
+                // SQLite has no comments, see `annotations`, nor enum types
+                comment: None,
+                enum_values: Vec::new(),
+                generated: hidden > 1,
+            });
+        }
+        primary_key.sort();
+        table.primary_key = primary_key.into_iter().map(|(_, column)| column).collect();
+
+        // Indexes backing the primary key are already covered by `primary_key`
+        for index in sqlx::query(r#"SELECT name, "unique", origin FROM pragma_index_list(?)"#)
+            .bind(&name)
+            .fetch_all(pool)
+            .await
+            .map_err(introspection_error)?
+        {
+            let origin: String = index.try_get("origin").map_err(introspection_error)?;
+            if origin == "pk" {
+                continue;
+            }
+            let index_name: String = index.try_get("name").map_err(introspection_error)?;
+            let unique: i64 = index.try_get("unique").map_err(introspection_error)?;
+            let columns = sqlx::query(
+                "SELECT name FROM pragma_index_info(?) WHERE name IS NOT NULL ORDER BY seqno",
+            )
+            .bind(&index_name)
+            .fetch_all(pool)
+            .await
+            .map_err(introspection_error)?
+            .iter()
+            .map(|column| column.try_get("name"))
+            .collect::<Result<Vec<String>, _>>()
+            .map_err(introspection_error)?;
+            if !columns.is_empty() {
+                table.indexes.push(Index {
+                    name: index_name,
+                    columns,
+                    unique: unique != 0,
+                });
+            }
+        }
+
+        // One row per column, `id` groups the columns of a foreign key and `seq` orders them
+        for fk in sqlx::query(
+            r#"SELECT id, "table", "from", "to" FROM pragma_foreign_key_list(?) ORDER BY id, seq"#,
+        )
+        .bind(&name)
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?
+        {
+            let id: i64 = fk.try_get("id").map_err(introspection_error)?;
+            let column: String = fk.try_get("from").map_err(introspection_error)?;
+            // NULL when the foreign key points at the primary key of the referenced table
+            let referenced_column: Option<String> =
+                fk.try_get("to").map_err(introspection_error)?;
+            if id as usize == table.foreign_keys.len() {
+                table.foreign_keys.push(ForeignKey {
+                    columns: Vec::new(),
+                    referenced_schema: None,
+                    referenced_table: fk.try_get("table").map_err(introspection_error)?,
+                    referenced_columns: Vec::new(),
+                });
+            }
+            let foreign_key = table.foreign_keys.last_mut().unwrap();
+            foreign_key.columns.push(column);
+            foreign_key.referenced_columns.extend(referenced_column);
+        }
+
+        schema.tables.push(table);
+    }
+
+    // Row counts are only known after `ANALYZE`, the `stat` of every index of a table starts
+    // with the number of rows
+    let analyzed =
+        sqlx::query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_stat1'")
+            .fetch_optional(pool)
+            .await
+            .map_err(introspection_error)?;
+    if analyzed.is_some() {
+        for row in sqlx::query(
+            "SELECT tbl, MAX(CAST(stat AS INTEGER)) AS row_estimate FROM sqlite_stat1 GROUP BY tbl",
+        )
+        .fetch_all(pool)
+        .await
+        .map_err(introspection_error)?
+        {
+            let name: String = row.try_get("tbl").map_err(introspection_error)?;
+            let rows: Option<i64> = row.try_get("row_estimate").map_err(introspection_error)?;
+            if let Some(table) = schema.tables.iter_mut().find(|table| table.name == name) {
+                table.row_estimate = rows;
+            }
+        }
+    }
+
+    // Fill in the primary keys for foreign keys that did not name the referenced columns
+    let primary_keys: Vec<(String, Vec<String>)> = schema
+        .tables
+        .iter()
+        .map(|table| (table.name.clone(), table.primary_key.clone()))
+        .collect();
+    for foreign_key in schema
+        .tables
+        .iter_mut()
+        .flat_map(|table| table.foreign_keys.iter_mut())
+        .filter(|foreign_key| foreign_key.referenced_columns.is_empty())
+    {
+        if let Some((_, primary_key)) = primary_keys
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&foreign_key.referenced_table))
+        {
+            foreign_key.referenced_columns = primary_key.clone();
+        }
+    }
+
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use sqlx::Executor;
+
+    #[tokio::test]
+    async fn test_load_sqlite_schema() {
+        // A single connection so every query sees the same in-memory database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        pool.execute(
+            "CREATE TABLE classes (id INTEGER PRIMARY KEY, title TEXT NOT NULL UNIQUE);
+             CREATE TABLE grades (student_id INTEGER, course TEXT, score REAL,
+                 PRIMARY KEY (course, student_id));
+             CREATE INDEX grades_score ON grades (score);
+             CREATE TABLE students (id INTEGER PRIMARY KEY, class_id INTEGER REFERENCES classes);
+             CREATE TABLE results (course TEXT, student_id INTEGER,
+                 FOREIGN KEY (student_id, course) REFERENCES grades (student_id, course));
+             CREATE VIEW top_grades AS SELECT course, MAX(score) AS best FROM grades
+                 GROUP BY course;
+             CREATE TABLE totals (net REAL, tax REAL,
+                 gross REAL GENERATED ALWAYS AS (net + tax) VIRTUAL);
+             INSERT INTO grades VALUES (1, 'math', 4.0), (2, 'math', 5.0);
+             ANALYZE;",
+        )
+        .await
+        .unwrap();
+
+        let schema = load(&pool).await.unwrap();
+        assert_eq!(schema.tables.len(), 6);
+        let classes = &schema.tables[0];
+        assert_eq!(classes.name, "classes");
+        assert_eq!(classes.primary_key, vec!["id"]);
+        assert!(!classes.columns[1].nullable);
+        assert_eq!(classes.indexes[0].columns, vec!["title"]);
+        assert!(classes.indexes[0].unique);
+        let grades = &schema.tables[1];
+        assert_eq!(grades.primary_key, vec!["course", "student_id"]);
+        assert_eq!(grades.indexes.len(), 1);
+        assert_eq!(grades.row_estimate, Some(2));
+        assert_eq!(classes.row_estimate, None);
+        assert!(!grades.indexes[0].unique);
+        let results = &schema.tables[2];
+        assert_eq!(
+            results.foreign_keys,
+            vec![ForeignKey {
+                columns: vec!["student_id".to_string(), "course".to_string()],
+                referenced_schema: None,
+                referenced_table: "grades".to_string(),
+                referenced_columns: vec!["student_id".to_string(), "course".to_string()],
+            }]
+        );
+        let students = &schema.tables[3];
+        assert_eq!(students.foreign_keys[0].columns, vec!["class_id"]);
+        assert_eq!(students.foreign_keys[0].referenced_table, "classes");
+        assert_eq!(students.foreign_keys[0].referenced_columns, vec!["id"]);
+        let top_grades = &schema.tables[4];
+        assert_eq!(top_grades.kind, TableKind::View);
+        assert_eq!(top_grades.columns[1].name, "best");
+        let totals = &schema.tables[5];
+        assert_eq!(totals.kind, TableKind::Table);
+        assert_eq!(totals.columns.len(), 3);
+        assert!(!totals.columns[1].generated);
+        assert!(totals.columns[2].generated);
+    }
+}
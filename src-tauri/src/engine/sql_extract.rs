@@ -0,0 +1,270 @@
+use sqlparser::parser::Parser;
+
+use super::config::DbType;
+use super::errors::AppError;
+use super::sql_guard;
+
+// Words a statement we accept from the model can start with
+const STATEMENT_STARTS: &[&str] = &["with", "select", "values", "explain"];
+
+/// Pull the SQL statement out of a model answer. `prompt` is the rendered prompt and
+/// `question` the user question, llama-cli may echo them before the answer.
+/// Fenced code blocks are searched first, then the plain text. The first candidate that
+/// parses as a single statement for `db_type` is returned, without a trailing `;`.
+pub fn extract_sql(
+    response: &str,
+    prompt: &str,
+    question: &str,
+    db_type: &DbType,
+) -> Result<String, AppError> {
+    let answer = strip_echo(response, prompt, question);
+
+    let blocks = fenced_blocks(answer);
+    let texts = blocks.iter().copied().chain(std::iter::once(answer));
+    for candidate in texts.flat_map(|text| candidates(text, db_type)) {
+        if parses(&candidate, db_type) {
+            return Ok(candidate);
+        }
+    }
+    Err(AppError::ExecutionError(
+        "Failed to extract SQL query from AI response".to_string(),
+    ))
+}
+
+// Drop everything up to the end of an echoed prompt, or of the echoed question when the
+// echo was not verbatim, so words like "select" in the question are not mistaken for SQL
+fn strip_echo<'a>(response: &'a str, prompt: &str, question: &str) -> &'a str {
+    for echo in [prompt.trim(), question.trim()] {
+        if echo.is_empty() {
+            continue;
+        }
+        if let Some(start) = response.rfind(echo) {
+            return &response[start + echo.len()..];
+        }
+    }
+    response
+}
+
+// Contents of ``` fenced blocks, blocks tagged as SQL first. An unclosed fence runs to the end.
+fn fenced_blocks(text: &str) -> Vec<&str> {
+    let mut tagged = Vec::new();
+    let mut untagged = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("```") {
+        let after = &rest[open + 3..];
+        let (info, body) = match after.find('\n') {
+            Some(newline) => (&after[..newline], &after[newline + 1..]),
+            None => ("", after),
+        };
+        let (block, next) = match body.find("```") {
+            Some(close) => (&body[..close], &body[close + 3..]),
+            None => (body, ""),
+        };
+        let info = info.trim().to_lowercase();
+        if info.contains("sql") {
+            tagged.push(block);
+        } else {
+            untagged.push(block);
+        }
+        rest = next;
+    }
+    tagged.extend(untagged);
+    tagged
+}
+
+// Every statement in `text` starting with one of STATEMENT_STARTS, ending at the first `;`
+// outside literals and comments. Without a `;` the statement may be followed by prose, so
+// it is also offered cut at each line end, longest first.
+fn candidates(text: &str, db_type: &DbType) -> Vec<String> {
+    let mut found = Vec::new();
+    for start in statement_starts(text) {
+        let statement = &text[start..];
+        match statement_end(statement, db_type) {
+            Some(end) => found.push(statement[..end].trim().to_string()),
+            None => {
+                found.push(statement.trim().to_string());
+                let mut ends: Vec<usize> = statement.match_indices('\n').map(|(i, _)| i).collect();
+                ends.reverse();
+                found.extend(
+                    ends.into_iter()
+                        .map(|end| statement[..end].trim().to_string()),
+                );
+            }
+        }
+    }
+    found.retain(|candidate| !candidate.is_empty());
+    found
+}
+
+// Byte offsets of the statement start words, matched as whole words ignoring case
+fn statement_starts(text: &str) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut word_start = None;
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        if c.is_alphanumeric() || c == '_' {
+            word_start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = word_start.take() {
+            let word = &text[start..i];
+            let preceded_by_word = text[..start].ends_with(['.', '$', '@']);
+            if !preceded_by_word
+                && STATEMENT_STARTS
+                    .iter()
+                    .any(|s| word.eq_ignore_ascii_case(s))
+            {
+                starts.push(start);
+            }
+        }
+    }
+    starts
+}
+
+// Offset of the `;` ending the statement, skipping string literals, quoted identifiers
+// and comments. None when the statement is not terminated.
+fn statement_end(sql: &str, db_type: &DbType) -> Option<usize> {
+    let backslash_escapes = matches!(db_type, DbType::MySQL);
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b';' => return Some(i),
+            quote @ (b'\'' | b'"' | b'`') => {
+                i += 1;
+                while i < bytes.len() {
+                    if backslash_escapes && bytes[i] == b'\\' {
+                        i += 2;
+                        continue;
+                    }
+                    if bytes[i] == quote {
+                        // A doubled quote is an escaped quote
+                        if bytes.get(i + 1) == Some(&quote) {
+                            i += 2;
+                            continue;
+                        }
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += 2;
+                while i < bytes.len() && !(bytes[i] == b'*' && bytes.get(i + 1) == Some(&b'/')) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn parses(sql: &str, db_type: &DbType) -> bool {
+    let dialect = sql_guard::dialect(db_type);
+    matches!(Parser::parse_sql(dialect.as_ref(), sql), Ok(statements) if statements.len() == 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPT: &str = "<|system|>\nYou are a helpful assistant. You will generate proper SQL statements for PostgreSQL.<|end|>\n<|user|>\nselect the names of all students<|end|>\n<|assistant|>\n";
+    const QUESTION: &str = "select the names of all students";
+
+    // Answers produced by the local models, paired with the SQL that should come out
+    const CORPUS: &[(&str, &str)] = &[
+        (
+            "SELECT name FROM students;",
+            "SELECT name FROM students",
+        ),
+        (
+            " Here is the SQL query to get all students:\n\n```sql\nSELECT *\nFROM students\nWHERE score > 80;\n```\n\nThis query selects every column.",
+            "SELECT *\nFROM students\nWHERE score > 80",
+        ),
+        (
+            "To select the students you can use:\n\nSELECT name FROM students ORDER BY name\n\nThis will return the names in alphabetical order.",
+            "SELECT name FROM students ORDER BY name",
+        ),
+        (
+            "```\nWITH top AS (\n  SELECT student_id, AVG(score) AS avg_score\n  FROM grades GROUP BY student_id\n)\nSELECT s.name, t.avg_score FROM students s JOIN top t ON t.student_id = s.id\n```",
+            "WITH top AS (\n  SELECT student_id, AVG(score) AS avg_score\n  FROM grades GROUP BY student_id\n)\nSELECT s.name, t.avg_score FROM students s JOIN top t ON t.student_id = s.id",
+        ),
+        (
+            "SELECT name FROM students WHERE note = 'late; excused' AND name <> 'O''Brien'; SELECT 2;",
+            "SELECT name FROM students WHERE note = 'late; excused' AND name <> 'O''Brien'",
+        ),
+        (
+            "I will select the right table first. select name from students where id in (select student_id from grades);",
+            "select name from students where id in (select student_id from grades)",
+        ),
+        (
+            "```python\nrows = cursor.execute(query)\n```\n```sql\nVALUES (1, 'a'), (2, 'b');\n```",
+            "VALUES (1, 'a'), (2, 'b')",
+        ),
+        (
+            "EXPLAIN SELECT * FROM students WHERE id = 1; -- check the plan",
+            "EXPLAIN SELECT * FROM students WHERE id = 1",
+        ),
+        (
+            "SELECT COUNT(*) FROM students -- how many; rows\n;",
+            "SELECT COUNT(*) FROM students -- how many; rows",
+        ),
+        (
+            "```sql\nSELECT name FROM students\n",
+            "SELECT name FROM students",
+        ),
+        (
+            "SELECT s.name FROM students s WHERE s.select_count > 1;",
+            "SELECT s.name FROM students s WHERE s.select_count > 1",
+        ),
+    ];
+
+    #[test]
+    fn test_extract_corpus() {
+        for (response, expected) in CORPUS {
+            let sql = extract_sql(response, PROMPT, QUESTION, &DbType::PostgreSQL);
+            assert_eq!(sql.ok().as_deref(), Some(*expected), "{}", response);
+        }
+    }
+
+    #[test]
+    fn test_strips_echoed_prompt() {
+        let response = format!("{}SELECT name FROM students;", PROMPT);
+        assert_eq!(
+            extract_sql(&response, PROMPT, QUESTION, &DbType::PostgreSQL).unwrap(),
+            "SELECT name FROM students"
+        );
+        // The question alone parses as SQL, it must not be returned when echoed
+        let response = format!("{}\nSELECT name FROM students", QUESTION);
+        assert_eq!(
+            extract_sql(&response, "", QUESTION, &DbType::SQLite).unwrap(),
+            "SELECT name FROM students"
+        );
+    }
+
+    #[test]
+    fn test_mysql_backslash_escapes() {
+        let response = r"SELECT * FROM students WHERE name = 'it\'s; fine';";
+        assert_eq!(
+            extract_sql(response, "", "", &DbType::MySQL).unwrap(),
+            r"SELECT * FROM students WHERE name = 'it\'s; fine'"
+        );
+    }
+
+    #[test]
+    fn test_no_sql() {
+        for response in ["I cannot answer that.", "Please select a table first.", ""] {
+            assert!(extract_sql(response, PROMPT, QUESTION, &DbType::PostgreSQL).is_err());
+        }
+    }
+}
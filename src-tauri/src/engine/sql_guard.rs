@@ -0,0 +1,402 @@
+use std::ops::ControlFlow;
+
+use sqlparser::ast::{Query, SetExpr, Statement, Visit, Visitor};
+use sqlparser::dialect::{Dialect, MySqlDialect, PostgreSqlDialect, SQLiteDialect};
+use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
+
+use super::config::DbType;
+use super::errors::AppError;
+
+// SQLite pragmas that only read information, with or without an argument
+const READ_ONLY_PRAGMAS: &[&str] = &[
+    "table_info",
+    "table_xinfo",
+    "table_list",
+    "index_list",
+    "index_info",
+    "index_xinfo",
+    "foreign_key_list",
+    "foreign_key_check",
+    "integrity_check",
+    "quick_check",
+];
+
+// SQLite pragmas that only read information when given no argument, setting pragmas report
+// their value. Others such as `optimize`, `incremental_vacuum` or `wal_checkpoint` write.
+const READ_ONLY_QUERY_PRAGMAS: &[&str] = &[
+    "analysis_limit",
+    "application_id",
+    "auto_vacuum",
+    "automatic_index",
+    "busy_timeout",
+    "cache_size",
+    "cache_spill",
+    "cell_size_check",
+    "checkpoint_fullfsync",
+    "collation_list",
+    "compile_options",
+    "data_version",
+    "database_list",
+    "defer_foreign_keys",
+    "encoding",
+    "foreign_keys",
+    "freelist_count",
+    "fullfsync",
+    "function_list",
+    "hard_heap_limit",
+    "ignore_check_constraints",
+    "journal_mode",
+    "journal_size_limit",
+    "legacy_alter_table",
+    "locking_mode",
+    "max_page_count",
+    "mmap_size",
+    "module_list",
+    "page_count",
+    "page_size",
+    "pragma_list",
+    "query_only",
+    "read_uncommitted",
+    "recursive_triggers",
+    "reverse_unordered_selects",
+    "schema_version",
+    "secure_delete",
+    "soft_heap_limit",
+    "synchronous",
+    "temp_store",
+    "threads",
+    "trusted_schema",
+    "user_version",
+    "wal_autocheckpoint",
+];
+
+// Pick the sqlparser dialect matching the database type
+pub fn dialect(db_type: &DbType) -> Box<dyn Dialect> {
+    match db_type {
+        DbType::MySQL => Box::new(MySqlDialect {}),
+        DbType::PostgreSQL => Box::new(PostgreSqlDialect {}),
+        DbType::SQLite => Box::new(SQLiteDialect {}),
+    }
+}
+
+/// Parse `sql` for the given database and make sure it is a single read-only statement.
+/// Returns `AppError::StatementRejected` naming the construct that was refused.
+pub fn ensure_read_only(sql: &str, db_type: &DbType) -> Result<(), AppError> {
+    if let DbType::SQLite = db_type {
+        if let Some(result) = check_sqlite_pragma(sql) {
+            return result;
+        }
+    }
+
+    let dialect = dialect(db_type);
+    let statements = Parser::parse_sql(dialect.as_ref(), sql).map_err(|e| {
+        rejected(
+            "unparseable statement",
+            format!("could not parse the query as {} SQL: {}", db_type, e),
+        )
+    })?;
+
+    match statements.len() {
+        0 => Err(rejected(
+            "empty statement",
+            "no SQL statement was given".to_string(),
+        )),
+        1 => match statements.visit(&mut ReadOnlyVisitor) {
+            ControlFlow::Break(err) => Err(err),
+            ControlFlow::Continue(()) => Ok(()),
+        },
+        n => Err(rejected(
+            "multiple statements",
+            format!("{} statements were given, only one is allowed", n),
+        )),
+    }
+}
+
+fn rejected(construct: &str, reason: String) -> AppError {
+    AppError::StatementRejected {
+        construct: construct.to_string(),
+        reason,
+    }
+}
+
+// Walks every statement and query in the AST, including CTEs and subqueries,
+// and stops at the first construct that could modify the database
+struct ReadOnlyVisitor;
+
+impl Visitor for ReadOnlyVisitor {
+    type Break = AppError;
+
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<AppError> {
+        match statement {
+            Statement::Query(_)
+            | Statement::ExplainTable { .. }
+            | Statement::ShowFunctions { .. }
+            | Statement::ShowVariable { .. }
+            | Statement::ShowStatus { .. }
+            | Statement::ShowVariables { .. }
+            | Statement::ShowCreate { .. }
+            | Statement::ShowColumns { .. }
+            | Statement::ShowTables { .. }
+            | Statement::ShowCollation { .. } => ControlFlow::Continue(()),
+            // The explained statement is visited next, EXPLAIN ANALYZE of a write still runs it
+            Statement::Explain { .. } => ControlFlow::Continue(()),
+            Statement::Pragma {
+                name, value, is_eq, ..
+            } => {
+                let name = name.to_string().to_lowercase();
+                let pragma = name.rsplit('.').next().unwrap_or_default();
+                match check_pragma(pragma, value.is_some(), *is_eq) {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(e) => ControlFlow::Break(e),
+                }
+            }
+            other => ControlFlow::Break(rejected(
+                statement_kind(other),
+                "only read-only queries are allowed".to_string(),
+            )),
+        }
+    }
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<AppError> {
+        if !query.locks.is_empty() {
+            return ControlFlow::Break(rejected(
+                "SELECT ... FOR UPDATE/SHARE",
+                "row locking clauses are not allowed".to_string(),
+            ));
+        }
+        check_set_expr(&query.body)
+    }
+}
+
+// Only known introspection pragmas and settings read without a value are allowed, anything
+// else may write
+fn check_pragma(pragma: &str, has_value: bool, is_eq: bool) -> Result<(), AppError> {
+    let read_only = if has_value {
+        !is_eq && READ_ONLY_PRAGMAS.contains(&pragma)
+    } else {
+        READ_ONLY_PRAGMAS.contains(&pragma) || READ_ONLY_QUERY_PRAGMAS.contains(&pragma)
+    };
+    if read_only {
+        Ok(())
+    } else {
+        Err(rejected(
+            "PRAGMA write",
+            format!("PRAGMA {} would change database settings", pragma),
+        ))
+    }
+}
+
+// sqlparser only accepts literal pragma arguments, so SQLite pragmas are classified
+// from their tokens instead: PRAGMA [schema.]name [= value | (value)] [;]
+// Returns None when the statement is not a pragma.
+fn check_sqlite_pragma(sql: &str) -> Option<Result<(), AppError>> {
+    let tokens: Vec<Token> = Tokenizer::new(&SQLiteDialect {}, sql)
+        .tokenize()
+        .ok()?
+        .into_iter()
+        .filter(|t| !matches!(t, Token::Whitespace(_)))
+        .collect();
+    match tokens.first() {
+        Some(Token::Word(word)) if word.value.eq_ignore_ascii_case("pragma") => {}
+        _ => return None,
+    }
+
+    let statements = tokens
+        .split(|t| *t == Token::SemiColon)
+        .filter(|s| !s.is_empty())
+        .count();
+    if statements > 1 {
+        return Some(Err(rejected(
+            "multiple statements",
+            format!("{} statements were given, only one is allowed", statements),
+        )));
+    }
+
+    let malformed = || rejected("unparseable statement", "malformed PRAGMA".to_string());
+    let mut rest = tokens[1..].iter().take_while(|t| **t != Token::SemiColon);
+    let mut name = match rest.next() {
+        Some(Token::Word(word)) => word.value.to_lowercase(),
+        _ => return Some(Err(malformed())),
+    };
+    let mut next = rest.next();
+    if next == Some(&Token::Period) {
+        name = match rest.next() {
+            Some(Token::Word(word)) => word.value.to_lowercase(),
+            _ => return Some(Err(malformed())),
+        };
+        next = rest.next();
+    }
+
+    Some(match next {
+        None => check_pragma(&name, false, false),
+        Some(Token::Eq) => check_pragma(&name, true, true),
+        Some(Token::LParen) => check_pragma(&name, true, false),
+        _ => Err(malformed()),
+    })
+}
+
+// Reject `SELECT ... INTO` anywhere in a (possibly compound) query body
+fn check_set_expr(body: &SetExpr) -> ControlFlow<AppError> {
+    match body {
+        SetExpr::Select(select) if select.into.is_some() => ControlFlow::Break(rejected(
+            "SELECT ... INTO",
+            "selecting into a table creates or modifies data".to_string(),
+        )),
+        SetExpr::SetOperation { left, right, .. } => {
+            check_set_expr(left)?;
+            check_set_expr(right)
+        }
+        SetExpr::Insert(_) | SetExpr::Update(_) => ControlFlow::Break(rejected(
+            "data-modifying query",
+            "INSERT/UPDATE inside a query is not allowed".to_string(),
+        )),
+        _ => ControlFlow::Continue(()),
+    }
+}
+
+// Short human readable name of a refused statement
+fn statement_kind(statement: &Statement) -> &'static str {
+    match statement {
+        Statement::Insert(_) => "INSERT",
+        Statement::Update { .. } => "UPDATE",
+        Statement::Delete(_) => "DELETE",
+        Statement::Merge { .. } => "MERGE",
+        Statement::Truncate { .. } => "TRUNCATE",
+        Statement::Copy { .. } => "COPY",
+        Statement::CreateTable(_)
+        | Statement::CreateView { .. }
+        | Statement::CreateIndex(_)
+        | Statement::CreateSchema { .. }
+        | Statement::CreateDatabase { .. }
+        | Statement::CreateFunction { .. }
+        | Statement::CreateProcedure { .. }
+        | Statement::CreateSequence { .. }
+        | Statement::CreateType { .. }
+        | Statement::CreateExtension { .. }
+        | Statement::CreateRole { .. } => "CREATE",
+        Statement::AlterTable { .. }
+        | Statement::AlterIndex { .. }
+        | Statement::AlterView { .. }
+        | Statement::AlterRole { .. } => "ALTER",
+        Statement::Drop { .. }
+        | Statement::DropFunction { .. }
+        | Statement::DropProcedure { .. } => "DROP",
+        Statement::Grant { .. } | Statement::Revoke { .. } => "GRANT/REVOKE",
+        Statement::StartTransaction { .. }
+        | Statement::SetTransaction { .. }
+        | Statement::Commit { .. }
+        | Statement::Rollback { .. }
+        | Statement::Savepoint { .. }
+        | Statement::ReleaseSavepoint { .. } => "transaction control",
+        Statement::SetVariable { .. }
+        | Statement::SetRole { .. }
+        | Statement::SetTimeZone { .. }
+        | Statement::SetNames { .. }
+        | Statement::SetNamesDefault { .. } => "SET",
+        Statement::Call(_) | Statement::Execute { .. } => "CALL/EXECUTE",
+        Statement::AttachDatabase { .. } => "ATTACH",
+        Statement::LockTables { .. } => "LOCK TABLES",
+        Statement::Kill { .. } => "KILL",
+        _ => "non-SELECT statement",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refused(sql: &str, db_type: DbType) -> String {
+        match ensure_read_only(sql, &db_type) {
+            Err(AppError::StatementRejected { construct, .. }) => construct,
+            other => panic!("expected {:?} to be rejected, got {:?}", sql, other),
+        }
+    }
+
+    #[test]
+    fn test_allows_plain_queries() {
+        for sql in [
+            "SELECT * FROM students;",
+            "WITH t AS (SELECT id FROM a) SELECT * FROM t JOIN b ON b.id = t.id",
+            "SELECT name FROM a UNION SELECT name FROM b",
+            "EXPLAIN SELECT * FROM students",
+        ] {
+            assert!(
+                ensure_read_only(sql, &DbType::PostgreSQL).is_ok(),
+                "{}",
+                sql
+            );
+        }
+        assert!(ensure_read_only("SHOW TABLES", &DbType::MySQL).is_ok());
+        assert!(ensure_read_only("PRAGMA table_info(students)", &DbType::SQLite).is_ok());
+        assert!(ensure_read_only("PRAGMA main.index_list('students');", &DbType::SQLite).is_ok());
+        assert!(ensure_read_only("PRAGMA user_version", &DbType::SQLite).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_writes() {
+        assert_eq!(
+            refused("DELETE FROM students", DbType::PostgreSQL),
+            "DELETE"
+        );
+        assert_eq!(
+            refused("update students set a = 1", DbType::MySQL),
+            "UPDATE"
+        );
+        assert_eq!(refused("DROP TABLE students", DbType::SQLite), "DROP");
+        assert_eq!(
+            refused("SELECT 1; DELETE FROM students", DbType::PostgreSQL),
+            "multiple statements"
+        );
+        assert_eq!(
+            refused("SELECT * INTO backup FROM students", DbType::PostgreSQL),
+            "SELECT ... INTO"
+        );
+        assert_eq!(
+            refused("EXPLAIN ANALYZE DELETE FROM students", DbType::PostgreSQL),
+            "DELETE"
+        );
+        assert_eq!(
+            refused("PRAGMA user_version = 3", DbType::SQLite),
+            "PRAGMA write"
+        );
+        assert_eq!(
+            refused("PRAGMA journal_mode(WAL)", DbType::SQLite),
+            "PRAGMA write"
+        );
+        for sql in [
+            "PRAGMA optimize",
+            "PRAGMA incremental_vacuum",
+            "PRAGMA main.wal_checkpoint;",
+            "PRAGMA shrink_memory",
+        ] {
+            assert_eq!(refused(sql, DbType::SQLite), "PRAGMA write", "{}", sql);
+        }
+        assert_eq!(
+            refused("PRAGMA table_info(a); DELETE FROM a", DbType::SQLite),
+            "multiple statements"
+        );
+        assert_eq!(
+            refused("SELECT * FROM students FOR UPDATE", DbType::PostgreSQL),
+            "SELECT ... FOR UPDATE/SHARE"
+        );
+    }
+
+    #[test]
+    fn test_rejects_data_modifying_cte() {
+        let sql = "WITH moved AS (DELETE FROM a RETURNING *) SELECT * FROM moved";
+        // sqlparser cannot parse DELETE inside a CTE, so it never reaches the database
+        assert_eq!(refused(sql, DbType::PostgreSQL), "unparseable statement");
+        for sql in [
+            "WITH moved AS (INSERT INTO a VALUES (1) RETURNING *) SELECT * FROM moved",
+            "WITH changed AS (UPDATE a SET x = 1 RETURNING *) SELECT * FROM changed",
+        ] {
+            assert_eq!(
+                refused(sql, DbType::PostgreSQL),
+                "data-modifying query",
+                "{}",
+                sql
+            );
+        }
+    }
+}
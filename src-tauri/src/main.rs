@@ -5,100 +5,172 @@
 mod engine;
 
 use engine::{
-    config::{Config, DbType},
+    cancel::{until_cancelled, Cancellations},
+    config::Config,
     errors::AppError,
-    Engine,
+    schema::{annotations::Annotations, TableKind},
+    Attempt, Engine,
 };
 use serde::Deserialize;
 use serde::Serialize;
-use serde_json::Value;
-use std::{
-    env::consts::OS,
-    io::{self, Write},
-    sync::Arc,
-};
-use tauri::{command, App, State};
-use tokio::sync::Mutex;
+use std::{env::consts::OS, sync::Arc};
+use tauri::{command, Manager, RunEvent, State};
+use tokio::sync::{Mutex, MutexGuard};
+use tokio_util::sync::CancellationToken;
 
 #[derive(Serialize, Deserialize)]
 struct Response<T> {
     sql: String,
     question: String,
     data: T,
+    // Generated queries tried by `ask`, failed ones carry the database error
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attempts: Vec<Attempt>,
+    // Tables described to the model for the question
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tables: Vec<String>,
+}
+
+// Payload of the `ai://token` event carrying text generated for a request
+#[derive(Serialize, Clone)]
+struct TokenEvent {
+    request_id: String,
+    token: String,
+}
+
+// A table as listed by `list_tables`, `describe_table` returns all of it
+#[derive(Serialize)]
+struct TableSummary {
+    schema: Option<String>,
+    name: String,
+    kind: TableKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row_estimate: Option<i64>,
+    columns: usize,
 }
 
 /**
- * Command to connect to a database configuration.
- * This loads a configuration into the engine and resolves the AI CLI path.
+ * Build a callback forwarding generated text to the frontend as `ai://token` events.
  */
-#[tauri::command]
-async fn connect_config(
-    engine: State<'_, Arc<Mutex<Engine>>>, // Shared engine state
-    data: String,
-    handle: tauri::AppHandle, // Tauri app handle for resolving resources
-) -> Result<String, AppError> {
-    // Determine the current platform (Windows, Linux, macOS)
-    let platform = OS;
+fn token_emitter(
+    handle: tauri::AppHandle,
+    request_id: Option<String>,
+) -> impl Fn(&str) + Send + Sync {
+    let request_id = request_id.unwrap_or_default();
+    move |token: &str| {
+        let _ = handle.emit_all(
+            "ai://token",
+            TokenEvent {
+                request_id: request_id.clone(),
+                token: token.to_string(),
+            },
+        );
+    }
+}
+
+/**
+ * Wait for the engine, another request may be holding it. Gives up when the request is cancelled.
+ */
+async fn lock_engine<'a>(
+    engine: &'a Mutex<Engine>,
+    cancel: &CancellationToken,
+) -> Result<MutexGuard<'a, Engine>, AppError> {
+    until_cancelled(cancel, engine.lock())
+        .await
+        .ok_or(AppError::Cancelled)
+}
 
-    // Set base CLI resource file path
-    let mut resource_file = "bin/llama-cli".to_string();
+/**
+ * Resolve the path of a bundled llama.cpp binary for the current platform.
+ */
+fn resolve_binary(handle: &tauri::AppHandle, name: &str) -> String {
+    // Set base resource file path
+    let mut resource_file = format!("bin/{}", name);
 
     // Append `.exe` if the platform is Windows
-    if platform == "windows" {
+    if OS == "windows" {
         resource_file.push_str(".exe");
     }
 
-    // Resolve the AI CLI resource path
+    // Resolve the resource path
     let resource_path = handle
         .path_resolver()
         .resolve_resource(&resource_file)
         .expect("failed to resolve resource");
 
+    match resource_path.to_str() {
+        Some(res) => res.to_string(),
+        None => "".to_string(),
+    }
+}
+
+/**
+ * Command to connect to a database configuration.
+ * This loads a configuration into the engine, resolves the AI binaries and starts the AI server.
+ */
+#[tauri::command]
+async fn connect_config(
+    engine: State<'_, Arc<Mutex<Engine>>>, // Shared engine state
+    data: String,
+    handle: tauri::AppHandle, // Tauri app handle for resolving resources
+) -> Result<String, AppError> {
     // Deserialize the incoming JSON data into a Config object
     let mut config: Config = match serde_json::from_str(&data) {
         Ok(config) => config,
         Err(e) => return Err(AppError::ConfigError(e.to_string())),
     };
 
-    // Set the AI CLI path
-    config.ai_cli_path = match resource_path.to_str() {
-        Some(res) => res.to_string(),
+    // Set the AI CLI and server paths
+    config.ai_cli_path = resolve_binary(&handle, "llama-cli");
+    config.ai_server_path = resolve_binary(&handle, "llama-server");
+
+    // Set the app data directory holding user prompt templates
+    config.data_dir = match handle.path_resolver().app_data_dir() {
+        Some(dir) => dir.to_string_lossy().to_string(),
         None => "".to_string(),
     };
 
     // Lock the engine and load the configuration
     let mut engine = engine.lock().await;
     match engine.load_config(config).await {
-        Ok(_) => Ok("{\"msg\": \"success\"}".to_string()),
+        Ok(warning) => Ok(serde_json::json!({ "msg": "success", "warning": warning }).to_string()),
         Err(e) => Err(AppError::ConfigError(e.to_string())),
     }
 }
 
 /**
  * Command to ask a question, retrieve an SQL query, and execute it.
- * Returns both the SQL query and its execution result.
+ * Returns both the SQL query and its execution result, with the queries tried before it.
+ * The SQL is streamed as `ai://token` events tagged with `request_id` while it is generated,
+ * and the request can be stopped with `cancel(request_id)`.
  */
 #[command]
-async fn ask(engine: State<'_, Arc<Mutex<Engine>>>, question: String) -> Result<String, AppError> {
-    let mut engine = engine.lock().await;
-
-    // Generate SQL query from AI model based on the question
-    let sql = &mut engine
-        .ask_for_sql(question.to_string())
-        .await
-        .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+async fn ask(
+    engine: State<'_, Arc<Mutex<Engine>>>,
+    cancellations: State<'_, Arc<Cancellations>>,
+    question: String,
+    request_id: Option<String>,
+    handle: tauri::AppHandle,
+) -> Result<String, AppError> {
+    let running = cancellations.register(request_id.clone());
+    let mut engine = lock_engine(&engine, running.token()).await?;
+    let on_token = token_emitter(handle, request_id);
 
-    // Execute the generated SQL query
-    let result = engine
-        .query(&sql.to_string())
-        .await
-        .map_err(|e| AppError::QueryError(e.to_string()))?;
+    // Generate SQL query from AI model based on the question and execute it,
+    // failing queries are repaired by the model when the config allows it
+    let answer = engine
+        .ask(question.to_string(), &on_token, running.token())
+        .await?;
 
     // Create response structure with the question, SQL query, and result
     let res = Response {
         question,
-        sql: sql.to_string(),
-        data: result,
+        sql: answer.sql,
+        data: answer.data,
+        attempts: answer.attempts,
+        tables: answer.tables,
     };
 
     // Serialize response into JSON
@@ -111,27 +183,33 @@ async fn ask(engine: State<'_, Arc<Mutex<Engine>>>, question: String) -> Result<
 
 /**
  * Command to ask for an SQL query based on a question without executing it.
- * Returns only the SQL query.
+ * Returns only the SQL query, streaming it as `ai://token` events like `ask`.
  */
 #[command]
 async fn ask_for_sql(
     engine: State<'_, Arc<Mutex<Engine>>>,
-    data: String,
+    cancellations: State<'_, Arc<Cancellations>>,
     question: String,
+    request_id: Option<String>,
+    handle: tauri::AppHandle,
 ) -> Result<String, AppError> {
-    let mut engine = engine.lock().await;
+    let running = cancellations.register(request_id.clone());
+    let mut engine = lock_engine(&engine, running.token()).await?;
+    let on_token = token_emitter(handle, request_id);
 
     // Generate SQL query from AI model based on the question
-    let result = &mut engine
-        .ask_for_sql(question.to_string())
+    let (sql, tables) = engine
+        .ask_for_sql(question.to_string(), &on_token, running.token())
         .await
-        .map_err(|e| AppError::EngineExecutionError(e.to_string()))?;
+        .map_err(|e| e.context(AppError::EngineExecutionError))?;
 
     // Create response structure with the SQL query
     let res = Response {
         question,
-        sql: result.to_string(),
+        sql,
         data: (),
+        attempts: Vec::new(),
+        tables,
     };
 
     // Serialize response into JSON
@@ -144,23 +222,31 @@ async fn ask_for_sql(
 
 /**
  * Command to execute a raw SQL query directly.
- * Returns the query result.
+ * Returns the query result, the query can be stopped with `cancel(request_id)`.
  */
 #[command]
-async fn query(engine: State<'_, Arc<Mutex<Engine>>>, sql: String) -> Result<String, AppError> {
-    let mut engine = engine.lock().await;
+async fn query(
+    engine: State<'_, Arc<Mutex<Engine>>>,
+    cancellations: State<'_, Arc<Cancellations>>,
+    sql: String,
+    request_id: Option<String>,
+) -> Result<String, AppError> {
+    let running = cancellations.register(request_id);
+    let engine = lock_engine(&engine, running.token()).await?;
 
     // Execute the SQL query
     let result = engine
-        .query(&sql.to_string())
+        .query(&sql.to_string(), running.token())
         .await
-        .map_err(|e| AppError::QueryError(e.to_string()))?;
+        .map_err(|e| e.context(AppError::QueryError))?;
 
     // Create response structure with the SQL query and result
     let res = Response {
         question: "".to_string(),
         sql,
         data: result,
+        attempts: Vec::new(),
+        tables: Vec::new(),
     };
 
     // Serialize response into JSON
@@ -171,6 +257,111 @@ async fn query(engine: State<'_, Arc<Mutex<Engine>>>, sql: String) -> Result<Str
     Ok(res_json)
 }
 
+/**
+ * Command to read the schema of the connected database again, e.g. after a migration.
+ * Returns the number of tables and whether the schema changed.
+ */
+#[command]
+async fn refresh_schema(engine: State<'_, Arc<Mutex<Engine>>>) -> Result<String, AppError> {
+    let mut engine = engine.lock().await;
+    let changed = engine.refresh_schema().await?;
+    Ok(serde_json::json!({
+        "tables": engine.schema().tables.len(),
+        "changed": changed,
+    })
+    .to_string())
+}
+
+/**
+ * Command to get the local annotations describing tables and columns of the connected database.
+ */
+#[command]
+async fn get_annotations(engine: State<'_, Arc<Mutex<Engine>>>) -> Result<String, AppError> {
+    let engine = engine.lock().await;
+    let annotations = engine.annotations()?;
+    serde_json::to_string(&annotations).map_err(|e| AppError::ExecutionError(e.to_string()))
+}
+
+/**
+ * Command to save the local annotations of the connected database, e.g. that `amt` is in cents.
+ * They are shown to the model as comments like the ones stored in the database.
+ */
+#[command]
+async fn save_annotations(
+    engine: State<'_, Arc<Mutex<Engine>>>,
+    data: String,
+) -> Result<String, AppError> {
+    let annotations: Annotations =
+        serde_json::from_str(&data).map_err(|e| AppError::ConfigError(e.to_string()))?;
+    let mut engine = engine.lock().await;
+    engine.save_annotations(&annotations).await?;
+    Ok("{\"msg\": \"success\"}".to_string())
+}
+
+/**
+ * Command to list the tables and views of the connected database, as the engine describes them
+ * to the model.
+ */
+#[command]
+async fn list_tables(engine: State<'_, Arc<Mutex<Engine>>>) -> Result<String, AppError> {
+    let engine = engine.lock().await;
+    let tables: Vec<TableSummary> = engine
+        .schema()
+        .tables
+        .iter()
+        .map(|table| TableSummary {
+            schema: table.schema.clone(),
+            name: table.name.clone(),
+            kind: table.kind,
+            comment: table.comment.clone(),
+            row_estimate: table.row_estimate,
+            columns: table.columns.len(),
+        })
+        .collect();
+    serde_json::to_string(&tables).map_err(|e| AppError::ExecutionError(e.to_string()))
+}
+
+/**
+ * Command to describe a table with its columns, keys and indexes. `schema` is the schema or
+ * database `list_tables` gave for the table, none for the default one.
+ */
+#[command]
+async fn describe_table(
+    engine: State<'_, Arc<Mutex<Engine>>>,
+    name: String,
+    schema: Option<String>,
+) -> Result<String, AppError> {
+    let engine = engine.lock().await;
+    let table = engine
+        .schema()
+        .table(schema.as_deref(), &name)
+        .ok_or_else(|| AppError::QueryError(format!("No table named {}", name)))?;
+    serde_json::to_string(table).map_err(|e| AppError::ExecutionError(e.to_string()))
+}
+
+/**
+ * Command to get the whole schema of the connected database.
+ */
+#[command]
+async fn get_schema(engine: State<'_, Arc<Mutex<Engine>>>) -> Result<String, AppError> {
+    let engine = engine.lock().await;
+    serde_json::to_string(engine.schema()).map_err(|e| AppError::ExecutionError(e.to_string()))
+}
+
+/**
+ * Command to cancel a running `ask`, `ask_for_sql` or `query` request.
+ * The model run is stopped and a running database query is cancelled on the server,
+ * the cancelled command then fails with a `Cancelled` error.
+ */
+#[command]
+fn cancel(
+    cancellations: State<'_, Arc<Cancellations>>,
+    request_id: String,
+) -> Result<String, AppError> {
+    let cancelled = cancellations.cancel(&request_id);
+    Ok(serde_json::json!({ "cancelled": cancelled }).to_string())
+}
+
 /**
  * Main entry point for the Tauri application.
  * Initializes the engine and registers commands.
@@ -183,12 +374,28 @@ fn main() {
     // Initialize Tauri application and register commands
     tauri::Builder::default()
         .manage(engine.clone()) // Manage shared engine state
+        .manage(Arc::new(Cancellations::default())) // Running requests, outside the engine lock
         .invoke_handler(tauri::generate_handler![
             connect_config,
             ask,
             ask_for_sql,
-            query
+            query,
+            refresh_schema,
+            get_annotations,
+            save_annotations,
+            list_tables,
+            describe_table,
+            get_schema,
+            cancel
         ]) // Register command handlers
-        .run(tauri::generate_context!()) // Run the Tauri application
-        .expect("error while running Tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running Tauri application")
+        .run(move |_app, event| {
+            // Stop the llama-server child process when the app exits
+            if let RunEvent::Exit = event {
+                tauri::async_runtime::block_on(async {
+                    engine.lock().await.shutdown().await;
+                });
+            }
+        });
 }